@@ -0,0 +1,55 @@
+use serde::Deserialize;
+use toml_migrate::{ConfigMigrator, Migrate};
+
+mod v1 {
+    use super::*;
+
+    #[derive(Debug, Deserialize, Migrate)]
+    #[migrate(version = 1)]
+    pub struct Config {
+        pub name: String,
+        pub timeout: u32,
+    }
+}
+
+const LATEST: i64 = 2;
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Migrate)]
+#[migrate(version = LATEST, from = v1::Config)]
+struct Config {
+    name: String,
+    timeout: u32,
+    retries: u8,
+}
+
+impl From<v1::Config> for Config {
+    fn from(prev: v1::Config) -> Self {
+        Self {
+            name: prev.name,
+            timeout: prev.timeout,
+            retries: 4,
+        }
+    }
+}
+
+fn main() {
+    let config_str = r#"
+        version = 1
+        name = "MyApp"
+        timeout = 60
+    "#;
+
+    let migrator = ConfigMigrator::new("version");
+
+    let (config, outcome) = migrator
+        .migrate_config::<Config>(config_str)
+        .expect("failed to read and/or migrate config");
+
+    if outcome.migrated() {
+        println!(
+            "Migrated from v{} to v{}! New config: {:?}",
+            outcome.from, outcome.to, config
+        );
+    }
+}
@@ -7,6 +7,7 @@ struct ConfigV1 {
     timeout: u32,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 struct ConfigV2 {
     name: String,
@@ -35,11 +36,14 @@ fn main() {
 
     let migrator = ConfigMigrator::new("version");
 
-    let (config, migrated) = migrator
+    let (config, outcome) = migrator
         .migrate_config::<ConfigV2>(config_str)
         .expect("failed to read and/or migrate config");
 
-    if migrated {
-        println!("Migration occured! New config: {:?}", config);
+    if outcome.migrated() {
+        println!(
+            "Migrated from v{} to v{}! New config: {:?}",
+            outcome.from, outcome.to, config
+        );
     }
 }
@@ -0,0 +1,162 @@
+//! Comparing two documents value by value, for dry runs and migration patches
+
+use toml_edit::{DocumentMut, Item, Key, TableLike, Value};
+
+/// One step of the path to a value: a key, or the index of a table in an array of tables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+pub(crate) type Path = Vec<Segment>;
+
+/// Writes a path as TOML dotted keys, with indices in brackets, like `servers[0].host`
+pub(crate) fn dotted(path: &[Segment]) -> String {
+    let mut dotted = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(key) => {
+                if !dotted.is_empty() {
+                    dotted.push('.');
+                }
+                dotted.push_str(&Key::new(key.as_str()).to_string());
+            }
+            Segment::Index(i) => dotted.push_str(&format!("[{i}]")),
+        }
+    }
+    dotted
+}
+
+/// A difference between the values of two documents
+pub(crate) enum Difference {
+    Added { path: Path, value: Value },
+    Removed { path: Path, value: Value },
+    Renamed { from: Path, to: Path, value: Value },
+    Changed { path: Path, from: Value, to: Value },
+}
+
+/// Every value and table of a document with its full path, in the order they appear
+pub(crate) struct Flattened {
+    pub(crate) values: Vec<(Path, Value)>,
+    pub(crate) tables: Vec<(Path, Item)>,
+}
+
+impl Flattened {
+    pub(crate) fn new(doc: &DocumentMut) -> Self {
+        let mut flattened = Self {
+            values: Vec::new(),
+            tables: Vec::new(),
+        };
+        flattened.add_table(doc.as_table(), &[]);
+        flattened
+    }
+
+    fn add_table(&mut self, table: &dyn TableLike, prefix: &[Segment]) {
+        for (key, item) in table.iter() {
+            let mut path = prefix.to_vec();
+            path.push(Segment::Key(key.to_owned()));
+
+            if let Item::ArrayOfTables(array) = item {
+                self.tables.push((path.clone(), item.clone()));
+                for (i, table) in array.iter().enumerate() {
+                    let mut path = path.clone();
+                    path.push(Segment::Index(i));
+                    self.tables.push((path.clone(), Item::Table(table.clone())));
+                    self.add_table(table, &path);
+                }
+            } else if let Some(table) = item.as_table_like() {
+                self.tables.push((path.clone(), item.clone()));
+                self.add_table(table, &path);
+            } else if let Some(value) = item.as_value() {
+                self.values.push((path, value.clone()));
+            }
+        }
+    }
+
+    pub(crate) fn value(&self, path: &[Segment]) -> Option<&Value> {
+        self.values
+            .iter()
+            .find(|(other, _)| other == path)
+            .map(|(_, value)| value)
+    }
+
+    pub(crate) fn table(&self, path: &[Segment]) -> Option<&Item> {
+        self.tables
+            .iter()
+            .find(|(other, _)| other == path)
+            .map(|(_, item)| item)
+    }
+}
+
+/// Compares every value of the two documents by path, pairing up removed and added values that are equal as renames
+///
+/// Changes come in the order of the original document, followed by the values that were added.
+pub(crate) fn diff(before: &Flattened, after: &Flattened) -> Vec<Difference> {
+    let mut added: Vec<_> = after
+        .values
+        .iter()
+        .filter(|(path, _)| before.value(path).is_none())
+        .map(Some)
+        .collect();
+
+    let mut differences = Vec::new();
+    for (path, value) in &before.values {
+        match after.value(path) {
+            Some(new) if values_eq(new, value) => {}
+            Some(new) => differences.push(Difference::Changed {
+                path: path.clone(),
+                from: value.clone(),
+                to: new.clone(),
+            }),
+            None => {
+                let renamed = added
+                    .iter_mut()
+                    .find(|added| added.is_some_and(|(_, new)| values_eq(new, value)))
+                    .and_then(Option::take);
+                differences.push(match renamed {
+                    Some((to, _)) => Difference::Renamed {
+                        from: path.clone(),
+                        to: to.clone(),
+                        value: value.clone(),
+                    },
+                    None => Difference::Removed {
+                        path: path.clone(),
+                        value: value.clone(),
+                    },
+                });
+            }
+        }
+    }
+
+    differences.extend(
+        added
+            .into_iter()
+            .flatten()
+            .map(|(path, value)| Difference::Added {
+                path: path.clone(),
+                value: value.clone(),
+            }),
+    );
+    differences
+}
+
+/// Whether two values hold the same data, ignoring formatting and comments
+fn values_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| values_eq(a, b))
+        }
+        (Value::InlineTable(a), Value::InlineTable(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, value)| b.get(key).is_some_and(|other| values_eq(value, other)))
+        }
+        _ => {
+            let (mut a, mut b) = (a.clone(), b.clone());
+            a.decor_mut().clear();
+            b.decor_mut().clear();
+            a.to_string() == b.to_string()
+        }
+    }
+}
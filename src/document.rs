@@ -0,0 +1,118 @@
+//! Migrating configs that were already parsed
+
+use serde::{de::Error as _, Serialize};
+use toml_edit::{DocumentMut, Item};
+
+use crate::{ConfigMigrator, Error, Migrate, MigrationOutcome};
+
+impl<'a> ConfigMigrator<'a> {
+    /// Handles the migration between versions of a config that was already parsed into a document or table
+    ///
+    /// Works like [`ConfigMigrator::migrate_config`], without parsing the config again, and errors in the same cases.
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// # use toml_migrate::toml_edit::DocumentMut;
+    /// # #[derive(Deserialize)] struct ConfigV1 {}
+    /// # #[derive(Deserialize)] struct ConfigV2 {}
+    /// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let doc: DocumentMut = "version = 1\n\n[meta]\nowner = \"ops\"\n".parse().unwrap();
+    /// assert_eq!(doc["meta"]["owner"].as_str(), Some("ops"));
+    ///
+    /// let (_, outcome) = ConfigMigrator::new("version").migrate_document::<ConfigV2>(doc).unwrap();
+    /// assert!(outcome.migrated());
+    /// ```
+    pub fn migrate_document<T: Migrate>(
+        &self,
+        doc: impl Into<DocumentMut>,
+    ) -> Result<(T, MigrationOutcome), Error> {
+        self.migrate_doc(self.config_table(doc.into())?)
+    }
+
+    /// Handles the migration between versions of a config that was already parsed into an item, which has to be a
+    /// table
+    ///
+    /// Errors in the same cases as [`ConfigMigrator::migrate_document`], or with [`Error::Deser`] if the item isn't a
+    /// table.
+    pub fn migrate_item<T: Migrate>(&self, item: Item) -> Result<(T, MigrationOutcome), Error> {
+        let table = item
+            .into_table()
+            .map_err(|_| toml_edit::de::Error::custom("config has to be a table"))?;
+        self.migrate_document(table)
+    }
+
+    /// Handles the migration between versions of a config that was parsed with the `toml` crate, which needs the
+    /// `toml` feature
+    ///
+    /// Errors in the same cases as [`ConfigMigrator::migrate_document`], or if the table could not be converted.
+    ///
+    /// ```
+    /// # #[cfg(feature = "toml")] {
+    /// # use serde::Deserialize;
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// #[derive(Deserialize)]
+    /// struct Config {
+    ///     name: String,
+    /// }
+    ///
+    /// build_migration_chain!(Config = 1);
+    ///
+    /// let table: toml::Table = "version = 1\nname = \"MyApp\"\n".parse().unwrap();
+    /// let (config, _) = ConfigMigrator::new("version").migrate_toml_table::<Config>(&table).unwrap();
+    ///
+    /// assert_eq!(config.name, "MyApp");
+    /// # }
+    /// ```
+    #[cfg(feature = "toml")]
+    pub fn migrate_toml_table<T: Migrate>(
+        &self,
+        table: &toml::Table,
+    ) -> Result<(T, MigrationOutcome), Error> {
+        self.migrate_document(toml_edit::ser::to_document(table)?)
+    }
+
+    /// Handles the migration between versions of a config that was already parsed, and writes the migrated config
+    /// back into the document
+    ///
+    /// The document is only changed if any migrations were performed, in the same way as
+    /// [`ConfigMigrator::migrate_config_preserving`] does, and errors are the same as well.
+    ///
+    /// ```
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// # use toml_migrate::toml_edit::DocumentMut;
+    /// # #[derive(Deserialize, Serialize)] struct ConfigV1 { timeout: u32 }
+    /// # #[derive(Deserialize, Serialize)] struct ConfigV2 { timeout: u32, retries: u8 }
+    /// # impl From<ConfigV1> for ConfigV2 {
+    /// #     fn from(prev: ConfigV1) -> Self { Self { timeout: prev.timeout, retries: 4 } }
+    /// # }
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let mut doc: DocumentMut = "version = 1\ntimeout = 60 # seconds\n".parse().unwrap();
+    /// ConfigMigrator::new("version")
+    ///     .migrate_document_preserving::<ConfigV2>(&mut doc)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(doc.to_string(), "version = 2\ntimeout = 60 # seconds\nretries = 4\n");
+    /// ```
+    pub fn migrate_document_preserving<T: Migrate + Serialize>(
+        &self,
+        doc: &mut DocumentMut,
+    ) -> Result<(T, MigrationOutcome), Error> {
+        let mut table = self.config_table(doc.clone())?;
+
+        let mut ignored = Vec::new();
+        let (config, outcome) =
+            self.migrate_doc_ignoring::<T>(table.clone(), Some(&mut ignored))?;
+        if outcome.migrated() {
+            let new = toml_edit::ser::to_document(&config)?;
+            self.write_back_migrated::<T>(&mut table, new, &T::VERSION, ignored);
+            self.replace_config_table(doc, table);
+        }
+
+        Ok((config, outcome))
+    }
+}
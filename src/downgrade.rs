@@ -0,0 +1,205 @@
+//! Downgrading configs to older versions of a chain
+
+use std::any::TypeId;
+
+use serde::Serialize;
+use toml_edit::{DocumentMut, Key, TableLike};
+
+use crate::{path, ConfigMigrator, Error, Migrate, MigrationOutcome, Version};
+
+impl<'a> ConfigMigrator<'a> {
+    /// Migrates a config to the latest version of the chain ending in `T`, and then downgrades it to the given version
+    ///
+    /// Every step between the two versions needs a downgrade, which is declared by marking the step with `rev` in
+    /// [`build_migration_chain!`](crate::build_migration_chain) and implementing `From<Next> for Prev`, or for
+    /// document-level steps by adding a function after a `|`, like `1 => v1_to_v2 | v2_to_v1`. Structs that get
+    /// downgraded to also have to implement `Serialize`.
+    ///
+    /// On success, returns a tuple with the downgraded config, in the target version's [`Migrate::FORMAT`] if it has
+    /// one and in the format it was read in otherwise, a [`MigrationOutcome`] whose path lists the
+    /// versions the config was downgraded through, and the dotted keys of any values that would be lost by the
+    /// downgrade. These are found by migrating the downgraded config, as it is written, back up again and comparing it
+    /// to the original. The downgraded config only has the keys of the target version, and the keys that the original
+    /// config ignored if the target version ignores them as well. Ignored keys that can't be kept are lost too.
+    /// A target of the latest version only migrates the config. Errors in the same cases as
+    /// [`ConfigMigrator::migrate_config_preserving`], with [`Error::UnreachableTarget`] if the target isn't a version of
+    /// the chain, or with [`Error::MissingDowngrade`] if a step has no downgrade.
+    ///
+    /// ```
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator, Error};
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV1 {
+    ///     timeout: u32,
+    /// }
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV2 {
+    ///     timeout: u32,
+    ///     retries: u8,
+    /// }
+    ///
+    /// impl From<ConfigV1> for ConfigV2 {
+    ///     fn from(prev: ConfigV1) -> Self {
+    ///         Self { timeout: prev.timeout, retries: 4 }
+    ///     }
+    /// }
+    ///
+    /// impl From<ConfigV2> for ConfigV1 {
+    ///     fn from(next: ConfigV2) -> Self {
+    ///         Self { timeout: next.timeout }
+    ///     }
+    /// }
+    ///
+    /// build_migration_chain!(ConfigV1 = 1, rev ConfigV2 = 2);
+    ///
+    /// let config_str = "version = 2\ntimeout = 60 # seconds\nretries = 8\n";
+    /// let (downgraded, _, lost) = ConfigMigrator::new("version")
+    ///     .downgrade_config::<ConfigV2>(config_str, 1)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(downgraded, "version = 1\ntimeout = 60 # seconds\n");
+    /// assert_eq!(lost, ["retries"]);
+    ///
+    /// let result = ConfigMigrator::new("version").downgrade_config::<ConfigV2>(config_str, 3);
+    /// assert!(matches!(result, Err(Error::UnreachableTarget { .. })));
+    /// ```
+    pub fn downgrade_config<T: Migrate + Serialize>(
+        &self,
+        config_str: &str,
+        target: impl Into<Version>,
+    ) -> Result<(String, MigrationOutcome, Vec<String>), Error> {
+        let format = self.input_format();
+        let mut doc = format.parse(config_str)?;
+        let mut table = self.config_table(doc.clone())?;
+        let mut ignored = Vec::new();
+        let (config, migrated) =
+            self.migrate_doc_ignoring::<T>(table.clone(), Some(&mut ignored))?;
+
+        let target = target.into();
+        let target = target
+            .clone()
+            .coerce(&T::VERSION)
+            .ok_or_else(|| Error::MalformedVersion {
+                found: target.to_string(),
+            })?;
+
+        let chain = T::versions();
+        let end = chain
+            .iter()
+            .rposition(|step| step.version.accepts(&target))
+            .ok_or_else(|| Error::UnreachableTarget {
+                found: migrated.from.clone(),
+                target,
+            })?;
+        let path: Vec<_> = chain[end..]
+            .iter()
+            .rev()
+            .map(|step| step.version.clone())
+            .collect();
+        let target = path[path.len() - 1].clone();
+        let target_format = chain[end].format.unwrap_or(format);
+
+        let latest = toml_edit::ser::to_document(&config)?;
+        let downgraded = downgrade_doc(config, &target)?;
+        let ignored_keys: Vec<_> = ignored.iter().map(|(key, _)| key.clone()).collect();
+        self.write_back_migrated::<T>(&mut table, downgraded, &target, ignored);
+
+        let (upgraded, _) = self.migrate_doc::<T>(table.clone())?;
+        let mut lost = lost_keys(
+            latest.as_table(),
+            toml_edit::ser::to_document(&upgraded)?.as_table(),
+        );
+        lost.extend(
+            ignored_keys
+                .into_iter()
+                .filter(|key| path::get(table.as_table(), key).is_none())
+                .map(|key| {
+                    key.iter()
+                        .map(|key| Key::new(key.as_str()).to_string())
+                        .collect::<Vec<_>>()
+                        .join(".")
+                }),
+        );
+        self.replace_config_table(&mut doc, table);
+
+        let outcome = MigrationOutcome {
+            from: migrated.from,
+            to: target,
+            path,
+            version_source: migrated.version_source,
+        };
+
+        Ok((format.convert(target_format, doc)?, outcome, lost))
+    }
+}
+
+/// Used by [`build_migration_chain!`](crate::build_migration_chain) to downgrade a config to the previous struct and
+/// then on to the target version
+pub fn downgrade<Prev, Next>(next: Next, target: &Version) -> Result<DocumentMut, Error>
+where
+    Prev: Migrate + Serialize,
+    Next: Into<Prev>,
+{
+    downgrade_doc::<Prev>(next.into(), target)
+}
+
+/// Turns a config into a document of the target version, which has to be this version or an older one
+fn downgrade_doc<T: Migrate + Serialize>(
+    config: T,
+    target: &Version,
+) -> Result<DocumentMut, Error> {
+    if T::VERSION == *target {
+        Ok(toml_edit::ser::to_document(&config)?)
+    } else if TypeId::of::<T>() == TypeId::of::<T::From>() {
+        let steps = T::DOC_MIGRATIONS;
+        let end = steps
+            .iter()
+            .position(|step| step.version == *target)
+            .ok_or_else(|| Error::UnreachableTarget {
+                found: T::VERSION,
+                target: target.clone(),
+            })?;
+
+        let mut doc = toml_edit::ser::to_document(&config)?;
+        for (i, step) in steps.iter().enumerate().skip(end).rev() {
+            let downgrade = step.downgrade.ok_or_else(|| Error::MissingDowngrade {
+                from: steps
+                    .get(i + 1)
+                    .map_or(T::VERSION, |next| next.version.clone()),
+                to: step.version.clone(),
+            })?;
+            downgrade(&mut doc);
+        }
+
+        Ok(doc)
+    } else {
+        let downgrade = T::DOWNGRADE.ok_or(Error::MissingDowngrade {
+            from: T::VERSION,
+            to: T::From::VERSION,
+        })?;
+        downgrade(config, target)
+    }
+}
+
+/// Dotted keys of every value in `original` that is missing or different in `round_trip`
+fn lost_keys(original: &dyn TableLike, round_trip: &dyn TableLike) -> Vec<String> {
+    let mut lost = Vec::new();
+
+    for (key, item) in original.iter() {
+        let prefix = Key::new(key).to_string();
+        match (item.as_table_like(), round_trip.get(key)) {
+            (Some(table), Some(other)) => match other.as_table_like() {
+                Some(other) => {
+                    let nested = lost_keys(table, other);
+                    lost.extend(nested.into_iter().map(|key| format!("{prefix}.{key}")));
+                }
+                None => lost.push(prefix),
+            },
+            (None, Some(other)) if item.to_string() == other.to_string() => {}
+            _ => lost.push(prefix),
+        }
+    }
+
+    lost
+}
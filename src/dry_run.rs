@@ -0,0 +1,159 @@
+//! Previewing what a migration would change
+
+use std::fmt;
+
+use serde::Serialize;
+use similar::TextDiff;
+use toml_edit::{DocumentMut, Value};
+
+use crate::diff::{diff, dotted, Difference, Flattened};
+use crate::{ConfigMigrator, Error, Migrate, MigrationOutcome};
+
+/// What a migration would do to a config, as returned by [`ConfigMigrator::dry_run`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DryRun {
+    /// Details about the migration
+    pub outcome: MigrationOutcome,
+    /// Every key the migration would add, remove, rename or change
+    pub changes: Vec<Change>,
+    /// Unified diff between the original and the migrated config, empty if nothing would change
+    pub diff: String,
+}
+
+/// A change to a single key of a config
+///
+/// Keys are written as TOML dotted keys, with the index of tables in arrays of tables in brackets, like
+/// `servers[0].host`. Values are written as TOML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The key is new
+    Added { key: String, value: String },
+    /// The key was removed
+    Removed { key: String, value: String },
+    /// The key was moved without changing its value
+    Renamed {
+        from: String,
+        to: String,
+        value: String,
+    },
+    /// The value of the key changed
+    Changed {
+        key: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { key, value } => write!(f, "added `{key}` = {value}"),
+            Self::Removed { key, value } => write!(f, "removed `{key}` = {value}"),
+            Self::Renamed { from, to, .. } => write!(f, "renamed `{from}` to `{to}`"),
+            Self::Changed { key, from, to } => write!(f, "changed `{key}` from {from} to {to}"),
+        }
+    }
+}
+
+impl<'a> ConfigMigrator<'a> {
+    /// Runs the migration without writing anything, and reports what it would change
+    ///
+    /// The migrated config is the one [`ConfigMigrator::migrate_config_preserving`] would return, and errors are the
+    /// same as well.
+    ///
+    /// ```
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, Change, ConfigMigrator};
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV1 {
+    ///     timeout_secs: u32,
+    /// }
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV2 {
+    ///     timeout: u32,
+    ///     retries: u8,
+    /// }
+    ///
+    /// impl From<ConfigV1> for ConfigV2 {
+    ///     fn from(prev: ConfigV1) -> Self {
+    ///         Self { timeout: prev.timeout_secs, retries: 4 }
+    ///     }
+    /// }
+    ///
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let dry_run = ConfigMigrator::new("version")
+    ///     .dry_run::<ConfigV2>("version = 1\ntimeout_secs = 60\n")
+    ///     .unwrap();
+    ///
+    /// for change in &dry_run.changes {
+    ///     println!("{change}");
+    /// }
+    /// assert_eq!(
+    ///     dry_run.changes[1],
+    ///     Change::Renamed { from: "timeout_secs".into(), to: "timeout".into(), value: "60".into() }
+    /// );
+    /// assert!(dry_run.diff.contains("+retries = 4\n"));
+    /// ```
+    pub fn dry_run<T: Migrate + Serialize>(&self, config_str: &str) -> Result<DryRun, Error> {
+        let (_, outcome, migrated) = self.migrate_config_preserving::<T>(config_str)?;
+        let Some(migrated) = migrated else {
+            return Ok(DryRun {
+                outcome,
+                changes: Vec::new(),
+                diff: String::new(),
+            });
+        };
+
+        let format = self.input_format();
+        let original_doc = format.parse(config_str)?;
+        let migrated_doc = T::FORMAT.unwrap_or(format).parse(&migrated)?;
+        let changes = diff_docs(&original_doc, &migrated_doc);
+
+        let diff = TextDiff::from_lines(config_str, &migrated)
+            .unified_diff()
+            .header("original", "migrated")
+            .to_string();
+
+        Ok(DryRun {
+            outcome,
+            changes,
+            diff,
+        })
+    }
+}
+
+/// Lists the changes between the two documents, using dotted keys and values written as TOML
+fn diff_docs(original: &DocumentMut, migrated: &DocumentMut) -> Vec<Change> {
+    diff(&Flattened::new(original), &Flattened::new(migrated))
+        .into_iter()
+        .map(|difference| match difference {
+            Difference::Added { path, value } => Change::Added {
+                key: dotted(&path),
+                value: value_repr(&value),
+            },
+            Difference::Removed { path, value } => Change::Removed {
+                key: dotted(&path),
+                value: value_repr(&value),
+            },
+            Difference::Renamed { from, to, value } => Change::Renamed {
+                from: dotted(&from),
+                to: dotted(&to),
+                value: value_repr(&value),
+            },
+            Difference::Changed { path, from, to } => Change::Changed {
+                key: dotted(&path),
+                from: value_repr(&from),
+                to: value_repr(&to),
+            },
+        })
+        .collect()
+}
+
+/// Writes a value as TOML without the whitespace and comments around it
+fn value_repr(value: &Value) -> String {
+    let mut value = value.clone();
+    value.decor_mut().clear();
+    value.to_string()
+}
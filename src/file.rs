@@ -0,0 +1,187 @@
+//! Migrating config files in place
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{ConfigMigrator, Error, Format, Migrate, MigrationOutcome};
+
+/// What to do with the original file when [`ConfigMigrator::migrate_file`] replaces it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupPolicy<'a> {
+    /// Don't keep a copy of the original file
+    None,
+    /// Copy the original file to a path with this extension added, e.g. `config.toml.bak`
+    Suffix(&'a str),
+    /// Copy the original file to a path with its version and `bak` added, e.g. `config.toml.v1.bak`
+    Versioned,
+}
+
+impl<'a> ConfigMigrator<'a> {
+    /// Sets what to do with the original file when [`ConfigMigrator::migrate_file`] replaces it
+    ///
+    /// Defaults to [`BackupPolicy::Suffix`] with `bak`.
+    #[must_use]
+    pub const fn with_backup(mut self, backup: BackupPolicy<'a>) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    /// Migrates a config file in place, keeping the formatting of the original file
+    ///
+    /// If any migrations were performed, the original file is backed up according to the [`BackupPolicy`], and then
+    /// atomically replaced by the migrated config, keeping its permissions. If the config is already at the latest
+    /// version, nothing is written. Errors in the same cases as [`ConfigMigrator::migrate_config_preserving`], or if
+    /// reading or writing any of the files failed.
+    ///
+    /// Unless a format was set with [`ConfigMigrator::with_format`], the file is read in the format matching its
+    /// extension, falling back to TOML. If the latest version has a different [`Migrate::FORMAT`], the migrated config
+    /// is written next to the original with that format's [`Format::extension`] instead, and the original is removed
+    /// after being backed up. This fails with an [`Error::Io`] if a file with the new name already exists.
+    ///
+    /// ```
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// # #[derive(Deserialize, Serialize)] struct ConfigV1 { timeout: u32 }
+    /// # #[derive(Deserialize, Serialize)] struct ConfigV2 { timeout: u32, retries: u8 }
+    /// # impl From<ConfigV1> for ConfigV2 {
+    /// #     fn from(prev: ConfigV1) -> Self { Self { timeout: prev.timeout, retries: 4 } }
+    /// # }
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let dir = std::env::temp_dir().join(format!("toml-migrate-doctest-migrate-file-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let path = dir.join("config.toml");
+    /// std::fs::write(&path, "version = 1\ntimeout = 60\n").unwrap();
+    ///
+    /// let (config, outcome) = ConfigMigrator::new("version")
+    ///     .migrate_file::<ConfigV2>(&path)
+    ///     .unwrap();
+    ///
+    /// assert!(outcome.migrated());
+    /// assert_eq!(std::fs::read_to_string(&path).unwrap(), "version = 2\ntimeout = 60\nretries = 4\n");
+    /// assert_eq!(std::fs::read_to_string(dir.join("config.toml.bak")).unwrap(), "version = 1\ntimeout = 60\n");
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    ///
+    /// A chain that moved from JSON to TOML renames the file when migrating it:
+    ///
+    /// ```
+    /// # #[cfg(feature = "json")] {
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, BackupPolicy, ConfigMigrator};
+    /// # #[derive(Deserialize, Serialize)] struct ConfigV1 { timeout: u32 }
+    /// # #[derive(Deserialize, Serialize)] struct ConfigV2 { timeout: u32, retries: u8 }
+    /// # #[derive(Deserialize, Serialize)] struct ConfigV3 { timeout: u32, retries: u8 }
+    /// # impl From<ConfigV1> for ConfigV2 {
+    /// #     fn from(prev: ConfigV1) -> Self { Self { timeout: prev.timeout, retries: 4 } }
+    /// # }
+    /// # impl From<ConfigV2> for ConfigV3 {
+    /// #     fn from(prev: ConfigV2) -> Self { Self { timeout: prev.timeout, retries: prev.retries } }
+    /// # }
+    /// build_migration_chain!(ConfigV1: Json = 1, ConfigV2: Json = 2, ConfigV3: Toml = 3);
+    ///
+    /// let dir = std::env::temp_dir().join(format!("toml-migrate-doctest-migrate-file-format-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("settings.json"), r#"{ "version": 1, "timeout": 60 }"#).unwrap();
+    ///
+    /// ConfigMigrator::new("version")
+    ///     .with_backup(BackupPolicy::None)
+    ///     .migrate_file::<ConfigV3>(dir.join("settings.json"))
+    ///     .unwrap();
+    ///
+    /// assert!(!dir.join("settings.json").exists());
+    /// assert_eq!(
+    ///     std::fs::read_to_string(dir.join("settings.toml")).unwrap(),
+    ///     "version = 3\ntimeout = 60\nretries = 4\n",
+    /// );
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// # }
+    /// ```
+    pub fn migrate_file<T: Migrate + Serialize>(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(T, MigrationOutcome), Error> {
+        let path = path.as_ref();
+        let config_str = fs::read_to_string(path)?;
+        let format = self
+            .format
+            .or_else(|| Format::from_path(path))
+            .unwrap_or_default();
+
+        let (config, outcome, migrated) = self.migrate_preserving::<T>(&config_str, format)?;
+        let Some(migrated) = migrated else {
+            return Ok((config, outcome));
+        };
+
+        let new_format = T::FORMAT.unwrap_or(format);
+        let new_path = if new_format == format {
+            path.to_owned()
+        } else {
+            let new_path = path.with_extension(new_format.extension());
+            if new_path.try_exists()? {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!(
+                        "can't rename config to `{}`, which already exists",
+                        new_path.display()
+                    ),
+                )
+                .into());
+            }
+            new_path
+        };
+
+        if let Some(backup_path) = self.backup_path(path, &outcome) {
+            fs::copy(path, backup_path)?;
+        }
+        replace_file(path, &new_path, migrated.as_bytes())?;
+        if new_path != path {
+            fs::remove_file(path)?;
+        }
+
+        Ok((config, outcome))
+    }
+
+    fn backup_path(&self, path: &Path, outcome: &MigrationOutcome) -> Option<PathBuf> {
+        let extension = match self.backup {
+            BackupPolicy::None => return None,
+            BackupPolicy::Suffix(suffix) => suffix.to_owned(),
+            BackupPolicy::Versioned => format!("v{}.bak", outcome.from),
+        };
+
+        let mut backup_path = path.as_os_str().to_owned();
+        backup_path.push(".");
+        backup_path.push(extension);
+        Some(backup_path.into())
+    }
+}
+
+/// Atomically writes a file with the permissions of the original one, by writing to a temporary file next to it and
+/// renaming it over the destination, which can be the original itself
+fn replace_file(original: &Path, path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let permissions = fs::metadata(original)?.permissions();
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = write_new_file(&temp_path, contents)
+        .and_then(|()| fs::set_permissions(&temp_path, permissions))
+        .and_then(|()| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    Ok(result?)
+}
+
+fn write_new_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
@@ -0,0 +1,330 @@
+//! File formats that configs can be read from
+//!
+//! Every format is converted into a TOML document, so that the version key and the migration chain work the same way
+//! no matter where the config came from.
+
+use std::path::Path;
+
+use serde::{
+    ser::{SerializeMap, SerializeSeq},
+    Serialize, Serializer,
+};
+use toml_edit::{DocumentMut, Item, TableLike, Value};
+
+use crate::{preserve, ConfigMigrator, Error, Migrate, MigrationOutcome};
+
+pub(crate) mod de;
+
+/// Format of a config file
+///
+/// Formats other than TOML are converted into TOML before migrating. Since TOML has no null, `null` values of keys are
+/// dropped, as if the key wasn't there, and configs with `null` inside an array fail to parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Format {
+    /// TOML, with comments and formatting kept when writing it back
+    #[default]
+    Toml,
+    /// JSON, which needs the `json` feature
+    #[cfg(feature = "json")]
+    Json,
+    /// YAML, which needs the `yaml` feature
+    ///
+    /// Anchors, aliases and `<<` merge keys are resolved while reading, so they are written back expanded.
+    ///
+    /// ```
+    /// # #[cfg(feature = "yaml")] {
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator, Format};
+    /// #[derive(Deserialize, Serialize)]
+    /// struct Limits {
+    ///     cpu: u32,
+    ///     memory: u32,
+    /// }
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV1 {
+    ///     defaults: Limits,
+    ///     worker: Limits,
+    /// }
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV2 {
+    ///     worker: Limits,
+    /// }
+    ///
+    /// impl From<ConfigV1> for ConfigV2 {
+    ///     fn from(prev: ConfigV1) -> Self {
+    ///         Self { worker: prev.worker }
+    ///     }
+    /// }
+    ///
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let config_str = "\
+    /// version: 1
+    /// defaults: &defaults
+    ///   cpu: 1
+    ///   memory: 512
+    /// worker:
+    ///   <<: *defaults
+    ///   memory: 1024
+    /// ";
+    ///
+    /// let (_, _, migrated) = ConfigMigrator::new("version")
+    ///     .with_format(Format::Yaml)
+    ///     .migrate_config_preserving::<ConfigV2>(config_str)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(migrated.unwrap(), "version: 2\nworker:\n  memory: 1024\n  cpu: 1\n");
+    /// # }
+    /// ```
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl Format {
+    /// Picks the format matching the extension of a file name, like `json` for `settings.json`
+    ///
+    /// Returns `None` for unknown extensions, and for formats whose feature isn't enabled.
+    #[must_use]
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            #[cfg(feature = "json")]
+            "json" => Some(Self::Json),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    /// The usual file extension for this format, without the dot
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Toml => "toml",
+            #[cfg(feature = "json")]
+            Self::Json => "json",
+            #[cfg(feature = "yaml")]
+            Self::Yaml => "yaml",
+        }
+    }
+
+    pub(crate) fn parse(self, config_str: &str) -> Result<DocumentMut, Error> {
+        match self {
+            Self::Toml => Ok(config_str.parse()?),
+            #[cfg(feature = "json")]
+            Self::Json => {
+                let table: de::RootTable = serde_json::from_str(config_str)?;
+                Ok(table.0)
+            }
+            #[cfg(feature = "yaml")]
+            Self::Yaml => parse_yaml(serde_yaml::Deserializer::from_str(config_str)),
+        }
+    }
+
+    /// Parses every document of a stream, for formats that can hold more than one
+    fn parse_stream(self, config_str: &str) -> Result<Vec<DocumentMut>, Error> {
+        match self {
+            #[cfg(feature = "yaml")]
+            Self::Yaml => serde_yaml::Deserializer::from_str(config_str)
+                .map(parse_yaml)
+                .collect(),
+            _ => Ok(vec![self.parse(config_str)?]),
+        }
+    }
+
+    /// Renders a document read in this format in the given one
+    ///
+    /// Tables are inline in documents read from formats other than TOML, so they are turned into standard tables when
+    /// the format changes.
+    pub(crate) fn convert(self, to: Format, mut doc: DocumentMut) -> Result<String, Error> {
+        if to != self {
+            preserve::expand_document(&mut doc);
+        }
+        to.render(&doc)
+    }
+
+    pub(crate) fn render(self, doc: &DocumentMut) -> Result<String, Error> {
+        match self {
+            Self::Toml => Ok(doc.to_string()),
+            #[cfg(feature = "json")]
+            Self::Json => {
+                let mut json =
+                    serde_json::to_string_pretty(&ItemRef(doc.as_item(), Datetimes::Strings))?;
+                json.push('\n');
+                Ok(json)
+            }
+            #[cfg(feature = "yaml")]
+            Self::Yaml => Ok(serde_yaml::to_string(&ItemRef(
+                doc.as_item(),
+                Datetimes::Strings,
+            ))?),
+        }
+    }
+}
+
+#[cfg(feature = "yaml")]
+fn parse_yaml(document: serde_yaml::Deserializer) -> Result<DocumentMut, Error> {
+    use serde::Deserialize;
+
+    let mut value = serde_yaml::Value::deserialize(document)?;
+    value.apply_merge()?;
+    let table = de::RootTable::deserialize(value)?;
+    Ok(table.0)
+}
+
+impl<'a> ConfigMigrator<'a> {
+    /// Migrates every config in a stream of documents, like a YAML file with several documents separated by `---`
+    ///
+    /// Each document has its own version. Formats that only hold one document per file are read like with
+    /// [`ConfigMigrator::migrate_config`], which also lists the errors.
+    ///
+    /// ```
+    /// # #[cfg(feature = "yaml")] {
+    /// # use serde::Deserialize;
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator, Format};
+    /// #[derive(Deserialize)]
+    /// struct ConfigV1 {
+    ///     replicas: u32,
+    /// }
+    ///
+    /// #[derive(Deserialize)]
+    /// struct ConfigV2 {
+    ///     replicas: u32,
+    ///     image: String,
+    /// }
+    ///
+    /// impl From<ConfigV1> for ConfigV2 {
+    ///     fn from(prev: ConfigV1) -> Self {
+    ///         Self { replicas: prev.replicas, image: "app:latest".to_owned() }
+    ///     }
+    /// }
+    ///
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let config_str = "\
+    /// version: 1
+    /// replicas: 3
+    /// ---
+    /// version: 2
+    /// replicas: 5
+    /// image: app:1.2
+    /// ";
+    ///
+    /// let configs = ConfigMigrator::new("version")
+    ///     .with_format(Format::Yaml)
+    ///     .migrate_config_stream::<ConfigV2>(config_str)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(configs[0].0.image, "app:latest");
+    /// assert_eq!(configs[1].0.image, "app:1.2");
+    /// assert!(configs[0].1.migrated() && !configs[1].1.migrated());
+    /// # }
+    /// ```
+    pub fn migrate_config_stream<T: Migrate>(
+        &self,
+        config_str: &str,
+    ) -> Result<Vec<(T, MigrationOutcome)>, Error> {
+        self.input_format()
+            .parse_stream(config_str)?
+            .into_iter()
+            .map(|doc| self.migrate_doc(self.config_table(doc)?))
+            .collect()
+    }
+}
+
+/// Serializes TOML data as plain values, with tables as maps and datetimes as strings
+pub(crate) fn serialize_item<S: Serializer>(item: &Item, serializer: S) -> Result<S::Ok, S::Error> {
+    ItemRef(item, Datetimes::Strings).serialize(serializer)
+}
+
+/// Serializes TOML data as plain values like [`serialize_item`], but keeps datetimes as TOML datetimes, for data that
+/// is written the way the config it came from would have been
+pub(crate) fn serialize_item_as_toml<S: Serializer>(
+    item: &Item,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    ItemRef(item, Datetimes::Toml).serialize(serializer)
+}
+
+/// How datetimes are serialized
+#[derive(Clone, Copy)]
+enum Datetimes {
+    Strings,
+    Toml,
+}
+
+fn serialize_value<S: Serializer>(
+    value: &Value,
+    datetimes: Datetimes,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Value::String(s) => serializer.serialize_str(s.value()),
+        Value::Integer(i) => serializer.serialize_i64(*i.value()),
+        Value::Float(f) => serializer.serialize_f64(*f.value()),
+        Value::Boolean(b) => serializer.serialize_bool(*b.value()),
+        Value::Datetime(dt) => match datetimes {
+            Datetimes::Strings => serializer.collect_str(dt.value()),
+            Datetimes::Toml => dt.value().serialize(serializer),
+        },
+        Value::Array(array) => {
+            let mut seq = serializer.serialize_seq(Some(array.len()))?;
+            for value in array.iter() {
+                seq.serialize_element(&ValueRef(value, datetimes))?;
+            }
+            seq.end()
+        }
+        Value::InlineTable(table) => serialize_table(table, datetimes, serializer),
+    }
+}
+
+fn serialize_table<S: Serializer>(
+    table: &dyn TableLike,
+    datetimes: Datetimes,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(table.len()))?;
+    for (key, item) in table.iter() {
+        map.serialize_entry(key, &ItemRef(item, datetimes))?;
+    }
+    map.end()
+}
+
+struct ItemRef<'a>(&'a Item, Datetimes);
+
+impl Serialize for ItemRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let datetimes = self.1;
+        match self.0 {
+            Item::None => serializer.serialize_none(),
+            Item::Value(value) => serialize_value(value, datetimes, serializer),
+            Item::Table(table) => serialize_table(table, datetimes, serializer),
+            Item::ArrayOfTables(array) => {
+                let mut seq = serializer.serialize_seq(Some(array.len()))?;
+                for table in array.iter() {
+                    seq.serialize_element(&TableRef(table, datetimes))?;
+                }
+                seq.end()
+            }
+        }
+    }
+}
+
+struct ValueRef<'a>(&'a Value, Datetimes);
+
+impl Serialize for ValueRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_value(self.0, self.1, serializer)
+    }
+}
+
+struct TableRef<'a>(&'a toml_edit::Table, Datetimes);
+
+impl Serialize for TableRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_table(self.0, self.1, serializer)
+    }
+}
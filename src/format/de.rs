@@ -0,0 +1,154 @@
+//! Reading other formats, or anything else serde can deserialize, into TOML documents
+
+use std::fmt;
+
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use toml_edit::{Array, Datetime, DocumentMut, InlineTable, Item, Value};
+
+const DATETIME_KEY: &str = "$__toml_private_datetime";
+
+/// A document deserialized from another format, which has to hold a table at its root
+///
+/// Also used to read configs from any other deserializer, see [`Versioned`](crate::Versioned) and
+/// [`MigrateSeed`](crate::MigrateSeed).
+pub(crate) struct RootTable(pub(crate) DocumentMut);
+
+impl<'de> Deserialize<'de> for RootTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match deserializer.deserialize_any(ValueVisitor)? {
+            Some(Value::InlineTable(table)) => {
+                let mut doc = DocumentMut::new();
+                for (key, value) in table {
+                    doc.insert(&key, Item::Value(value));
+                }
+                Ok(Self(doc))
+            }
+            _ => Err(de::Error::custom("config has to be a map at its root")),
+        }
+    }
+}
+
+/// A value deserialized from another format, or `None` if it was null
+struct OptionalValue(Option<Value>);
+
+impl<'de> Deserialize<'de> for OptionalValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor).map(Self)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Option<Value>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a value that can be represented in TOML")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(Some(v.into()))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Some(v.into()))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        let v = i64::try_from(v)
+            .map_err(|_| E::custom(format!("integer {v} is too large for TOML")))?;
+        Ok(Some(v.into()))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(Some(v.into()))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Some(v.into()))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut array = Array::new();
+        while let Some(OptionalValue(value)) = seq.next_element()? {
+            let value = value
+                .ok_or_else(|| de::Error::custom("null is not representable in TOML arrays"))?;
+            array.push(value);
+        }
+        Ok(Some(array.into()))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut table = InlineTable::new();
+        while let Some((MapKey(key), OptionalValue(value))) = map.next_entry()? {
+            if let Some(value) = value {
+                table.insert(&key, value);
+            }
+        }
+
+        // TOML's own deserializer passes datetimes as a map with a single private key
+        if let (1, Some(Value::String(datetime))) = (table.len(), table.get(DATETIME_KEY)) {
+            let datetime = datetime
+                .value()
+                .parse::<Datetime>()
+                .map_err(de::Error::custom)?;
+            return Ok(Some(datetime.into()));
+        }
+
+        Ok(Some(table.into()))
+    }
+}
+
+/// A map key, which TOML needs to be a string but other formats also allow to be a number or boolean
+struct MapKey(String);
+
+impl<'de> Deserialize<'de> for MapKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MapKeyVisitor).map(Self)
+    }
+}
+
+struct MapKeyVisitor;
+
+impl<'de> Visitor<'de> for MapKeyVisitor {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a string, number or boolean key")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(v.to_owned())
+    }
+}
@@ -0,0 +1,303 @@
+//! Finding the keys of a config that deserializing it didn't use
+
+use std::any::TypeId;
+
+use serde::de::{
+    self, value::StringDeserializer, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess,
+    MapAccess, SeqAccess, Visitor,
+};
+use serde::Deserialize;
+use toml_edit::{DocumentMut, Item};
+
+use crate::{chain_contains, path, version_error, Error, Migrate, Version};
+
+/// Keys that a config didn't use, as paths from the root of the document, with the values they had
+pub(crate) type Ignored = Vec<(Vec<String>, Item)>;
+
+/// Migrates a document of the given version to `T`, like [`Migrate::migrate_from_doc`]
+///
+/// If `ignored` is given, the keys that the struct the document was deserialized into ignored are added to it. Keys
+/// inside arrays aren't tracked.
+pub(crate) fn migrate_from_doc<T: Migrate>(
+    version: &Version,
+    mut doc: DocumentMut,
+    ignored: Option<&mut Ignored>,
+) -> Result<T, Error> {
+    if T::VERSION.accepts(version) {
+        deserialize(doc, ignored)
+    } else if !chain_contains::<T>(version) {
+        Err(version_error::<T>(version.clone()))
+    } else if TypeId::of::<T>() == TypeId::of::<T::From>() {
+        let start = T::DOC_MIGRATIONS
+            .iter()
+            .rposition(|step| step.version.accepts(version))
+            .ok_or_else(|| version_error::<T>(version.clone()))?;
+
+        for step in &T::DOC_MIGRATIONS[start..] {
+            (step.migrate)(&mut doc);
+        }
+
+        deserialize(doc, ignored)
+    } else {
+        migrate_from_doc::<T::From>(version, doc, ignored).and_then(T::migrate_from)
+    }
+}
+
+fn deserialize<T: DeserializeOwned>(
+    doc: DocumentMut,
+    ignored: Option<&mut Ignored>,
+) -> Result<T, Error> {
+    let Some(ignored) = ignored else {
+        return Ok(toml_edit::de::from_document(doc)?);
+    };
+
+    let mut paths = Vec::new();
+    let deserializer = Tracked {
+        de: toml_edit::de::Deserializer::from(doc.clone()),
+        path: Vec::new(),
+        ignored: &mut paths,
+    };
+    let config = T::deserialize(deserializer)?;
+
+    ignored.extend(paths.into_iter().filter_map(|path| {
+        let item = path::get(doc.as_table(), &path)?.clone();
+        Some((path, item))
+    }));
+    Ok(config)
+}
+
+/// A deserializer that records the path of every key that the type being deserialized ignored
+struct Tracked<'a, D> {
+    de: D,
+    path: Vec<String>,
+    ignored: &'a mut Vec<Vec<String>>,
+}
+
+impl<'a, D> Tracked<'a, D> {
+    fn wrap<V>(self, visitor: V) -> (D, TrackedVisitor<'a, V>) {
+        let visitor = TrackedVisitor {
+            visitor,
+            path: self.path,
+            ignored: self.ignored,
+        };
+        (self.de, visitor)
+    }
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error> {
+                let (de, visitor) = self.wrap(visitor);
+                de.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Tracked<'_, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_i128(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_u128(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        if !self.path.is_empty() {
+            self.ignored.push(self.path);
+        }
+        self.de.deserialize_ignored_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.de.is_human_readable()
+    }
+}
+
+/// Passes everything on to the wrapped visitor, tracking the values of maps and options
+struct TrackedVisitor<'a, V> {
+    visitor: V,
+    path: Vec<String>,
+    ignored: &'a mut Vec<Vec<String>>,
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method<E: de::Error>(self, v: $ty) -> Result<V::Value, E> {
+                self.visitor.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for TrackedVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.visitor.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool),
+        visit_i8(i8),
+        visit_i16(i16),
+        visit_i32(i32),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u8(u8),
+        visit_u16(u16),
+        visit_u32(u32),
+        visit_u64(u64),
+        visit_u128(u128),
+        visit_f32(f32),
+        visit_f64(f64),
+        visit_char(char),
+        visit_str(&str),
+        visit_borrowed_str(&'de str),
+        visit_string(String),
+        visit_bytes(&[u8]),
+        visit_borrowed_bytes(&'de [u8]),
+        visit_byte_buf(Vec<u8>),
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        self.visitor.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        self.visitor.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, de: D) -> Result<V::Value, D::Error> {
+        self.visitor.visit_some(Tracked {
+            de,
+            path: self.path,
+            ignored: self.ignored,
+        })
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, de: D) -> Result<V::Value, D::Error> {
+        self.visitor.visit_newtype_struct(Tracked {
+            de,
+            path: self.path,
+            ignored: self.ignored,
+        })
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        self.visitor.visit_seq(seq)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        self.visitor.visit_map(TrackedMap {
+            map,
+            key: None,
+            path: self.path,
+            ignored: self.ignored,
+        })
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+        self.visitor.visit_enum(data)
+    }
+}
+
+/// Map access that remembers each key, to track the value under it
+struct TrackedMap<'a, A> {
+    map: A,
+    key: Option<String>,
+    path: Vec<String>,
+    ignored: &'a mut Vec<Vec<String>>,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for TrackedMap<'_, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        self.map.next_key_seed(CaptureKey {
+            seed,
+            key: &mut self.key,
+        })
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, A::Error> {
+        let mut path = self.path.clone();
+        path.extend(self.key.take());
+        self.map.next_value_seed(TrackedSeed {
+            seed,
+            path,
+            ignored: self.ignored,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.map.size_hint()
+    }
+}
+
+/// Reads a map key as a string, and passes it on to the wrapped seed
+struct CaptureKey<'a, S> {
+    seed: S,
+    key: &'a mut Option<String>,
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for CaptureKey<'_, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<S::Value, D::Error> {
+        let key = String::deserialize(de)?;
+        *self.key = Some(key.clone());
+        self.seed.deserialize(StringDeserializer::new(key))
+    }
+}
+
+struct TrackedSeed<'a, S> {
+    seed: S,
+    path: Vec<String>,
+    ignored: &'a mut Vec<Vec<String>>,
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for TrackedSeed<'_, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<S::Value, D::Error> {
+        self.seed.deserialize(Tracked {
+            de,
+            path: self.path,
+            ignored: self.ignored,
+        })
+    }
+}
@@ -1,107 +1,950 @@
 #![doc = include_str!("../README.md")]
 
-use std::any::{Any, TypeId};
+use std::{
+    any::{Any, TypeId},
+    ops::{Bound, RangeBounds},
+};
 
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
-use toml_edit::DocumentMut;
+use toml_edit::{DocumentMut, Item};
+
+use crate::ignored::Ignored;
+
+mod diff;
+mod document;
+mod downgrade;
+mod dry_run;
+mod file;
+mod format;
+mod ignored;
+mod patch;
+mod path;
+mod preserve;
+mod sections;
+mod seed;
+mod serialize;
+mod table;
+mod target;
+mod version;
+mod versioned;
+
+pub use dry_run::{Change, DryRun};
+pub use file::BackupPolicy;
+pub use format::Format;
+pub use patch::PatchOperation;
+pub use sections::Sections;
+pub use seed::MigrateSeed;
+pub use target::Intermediate;
+pub use toml_edit;
+#[cfg(feature = "derive")]
+pub use toml_migrate_derive::Migrate;
+pub use version::{ParseSemVerError, SemVer, Version};
+pub use versioned::{DefaultVersionKey, VersionKey, Versioned};
+
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::downgrade::downgrade;
+    pub use crate::version::{is_newer, Lit};
+    pub use serde;
+}
 
 /// Trait used to determine config versions and migration order
 ///
 /// You should probably not implement this yourself, but instead use the [`build_migration_chain!`] macro.
-pub trait Migrate: From<Self::From> + DeserializeOwned + Any {
+pub trait Migrate: DeserializeOwned + Any {
     type From: Migrate;
-    const VERSION: i64;
+    const VERSION: Version;
+    /// Document-level migrations leading up to the oldest struct in the chain, oldest first
+    ///
+    /// Only used when `Self::From` is `Self`. Each step rewrites a document of its version into one of the next
+    /// step's version, with the last step producing a document that deserializes into `Self`.
+    const DOC_MIGRATIONS: &'static [DocMigration] = &[];
+    /// Version of the newest config in the chain, which is this one
+    const LATEST_VERSION: Version = Self::VERSION;
+    /// Version of the oldest config the chain can read, including document-level steps
+    ///
+    /// The oldest struct in the chain has to set this itself, which [`build_migration_chain!`] takes care of.
+    const OLDEST_VERSION: Version = Self::From::OLDEST_VERSION;
+    /// Turns this config into a document of the given older version, if the chain can downgrade it to `Self::From`
+    ///
+    /// Set by [`build_migration_chain!`] for steps marked with `rev`.
+    const DOWNGRADE: Option<Downgrade<Self>> = None;
+    /// Format that configs of this version are written in, if it isn't the same for every version of the chain
+    ///
+    /// Configs migrated to this version are written back in this format, no matter what format they were read in.
+    /// `None` keeps the format they were read in. Set by [`build_migration_chain!`] for steps written like
+    /// `ConfigV1: Json = 1`.
+    const FORMAT: Option<Format> = None;
+
+    fn migrate_from_doc(version: &Version, doc: DocumentMut) -> Result<Self, Error> {
+        ignored::migrate_from_doc(version, doc, None)
+    }
+
+    /// Converts the previous version of the config into this one, using its `From` or `TryFrom` implementation
+    fn migrate_from(prev: Self::From) -> Result<Self, Error>;
 
-    fn migrate_from_doc(version: i64, doc: DocumentMut) -> Result<Self, Error> {
-        if version == Self::VERSION {
-            Ok(toml_edit::de::from_document(doc)?)
-        } else if TypeId::of::<Self>() == TypeId::of::<Self::From>() {
-            Err(Error::NoValidVersion)
+    /// Every version in the chain ending in this struct, oldest first
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// # use toml_migrate::{build_migration_chain, Migrate, Version};
+    /// # use toml_migrate::toml_edit::DocumentMut;
+    /// # #[derive(Deserialize)] struct ConfigV2 {}
+    /// # #[derive(Deserialize)] struct ConfigV3 {}
+    /// # impl From<ConfigV2> for ConfigV3 { fn from(_: ConfigV2) -> Self { Self {} } }
+    /// # fn v1_to_v2(_: &mut DocumentMut) {}
+    /// build_migration_chain!(1 => v1_to_v2, ConfigV2 = 2, ConfigV3 = 3);
+    ///
+    /// let versions = ConfigV3::versions();
+    /// assert_eq!(versions.len(), 3);
+    /// assert_eq!(versions[0].version, Version::Int(1));
+    /// assert_eq!(versions[0].name, "v1_to_v2");
+    /// assert!(versions[2].name.ends_with("::ConfigV3"));
+    ///
+    /// assert_eq!(ConfigV3::OLDEST_VERSION, Version::Int(1));
+    /// assert_eq!(ConfigV3::LATEST_VERSION, Version::Int(3));
+    /// ```
+    fn versions() -> Vec<ChainStep> {
+        let mut versions = if TypeId::of::<Self>() == TypeId::of::<Self::From>() {
+            Self::DOC_MIGRATIONS
+                .iter()
+                .map(|step| ChainStep {
+                    version: step.version.clone(),
+                    name: step.name,
+                    format: Self::FORMAT,
+                })
+                .collect()
         } else {
-            Self::From::migrate_from_doc(version, doc).map(Into::into)
+            Self::From::versions()
+        };
+        versions.push(ChainStep {
+            version: Self::VERSION,
+            name: std::any::type_name::<Self>(),
+            format: Self::FORMAT,
+        });
+        versions
+    }
+}
+
+/// One version of a migration chain, as listed by [`Migrate::versions`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainStep {
+    pub version: Version,
+    /// Type name of the struct for this version, or the path of the function for document-level steps
+    pub name: &'static str,
+    /// Format of configs of this version, as set in [`Migrate::FORMAT`]
+    ///
+    /// Document-level steps share the format of the oldest struct in the chain.
+    pub format: Option<Format>,
+}
+
+/// Whether any step of the chain ending in `T` can read the given version
+fn chain_contains<T: Migrate>(version: &Version) -> bool {
+    T::versions()
+        .iter()
+        .any(|step| step.version.accepts(version))
+}
+
+/// Picks the right error for a version that the chain ending in `T` can't read
+fn version_error<T: Migrate>(found: Version) -> Error {
+    let oldest = T::OLDEST_VERSION;
+    let latest = T::LATEST_VERSION;
+
+    if found > latest {
+        Error::FutureVersion {
+            found,
+            oldest,
+            latest,
+        }
+    } else if found < oldest {
+        Error::OutdatedVersion {
+            found,
+            oldest,
+            latest,
+        }
+    } else {
+        Error::UnknownVersion {
+            found,
+            oldest,
+            latest,
         }
     }
 }
 
+/// Turns a config into a document of the given older version, as set in [`Migrate::DOWNGRADE`]
+pub type Downgrade<T> = fn(T, &Version) -> Result<DocumentMut, Error>;
+
+/// A migration step that rewrites the raw document of an old version, used for versions that no longer have a struct
+///
+/// You should probably not create these yourself, but instead list them in the [`build_migration_chain!`] macro.
+pub struct DocMigration {
+    /// Version of the documents this step applies to
+    pub version: Version,
+    /// Rewrites the document in place so that it matches the next version in the chain
+    pub migrate: fn(&mut DocumentMut),
+    /// Path of the function behind this step, as written in the chain
+    pub name: &'static str,
+    /// Rewrites a document of the next version in the chain back into one of this version, if the step can be undone
+    pub downgrade: Option<fn(&mut DocumentMut)>,
+}
+
 /// Struct that contains some configuration on how to migrate a config
 ///
 /// ```no_run
+/// # use serde::Deserialize;
+/// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+/// # #[derive(Deserialize)] struct ConfigV1 {}
+/// # #[derive(Deserialize)] struct ConfigV2 {}
+/// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+/// # build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+/// # let config_str = "";
 /// let migrator = ConfigMigrator::new("version").with_default_version(0);
 ///
-/// let (config, migration_occured) = migrator.migrate::<ConfigV2>(config_str).unwrap();
+/// let (config, outcome) = migrator.migrate_config::<ConfigV2>(config_str).unwrap();
 /// ```
 pub struct ConfigMigrator<'a> {
     version_key: &'a str,
-    default_version: Option<i64>,
+    version_aliases: Vec<VersionAlias<'a>>,
+    default_version: Option<Version>,
+    backup: BackupPolicy<'a>,
+    format: Option<Format>,
+    table: Option<&'a str>,
 }
 
 impl<'a> ConfigMigrator<'a> {
     /// Creates a new [`ConfigMigrator`] using the provided key to find the version of the config
+    ///
+    /// The key is read as a TOML dotted key, so a version inside a table can be found with e.g. `meta.schema_version`
+    /// or `package.metadata.my-tool.version`. Tables that only held the version are removed along with it.
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// #[derive(Deserialize)]
+    /// #[serde(deny_unknown_fields)]
+    /// struct Config {
+    ///     name: String,
+    /// }
+    ///
+    /// build_migration_chain!(Config = 3);
+    ///
+    /// let config_str = "name = \"MyApp\"\n\n[meta]\nschema_version = 3\n";
+    /// let (config, _) = ConfigMigrator::new("meta.schema_version")
+    ///     .migrate_config::<Config>(config_str)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(config.name, "MyApp");
+    /// ```
     #[must_use]
     pub const fn new(version_key: &'a str) -> Self {
         Self {
             version_key,
+            version_aliases: Vec::new(),
             default_version: None,
+            backup: BackupPolicy::Suffix("bak"),
+            format: None,
+            table: None,
         }
     }
 
-    /// Adds a default version to use if it cannot be read from the config file
+    /// Adds a fallback key to find the version under, for files written before the version key was renamed
+    ///
+    /// Aliases are tried in the order they were added, and only if the main version key is missing. When the config is
+    /// written back, the alias that provided the version is renamed to the main version key, keeping its place and
+    /// comments if both keys are in the same table.
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator, VersionAlias};
+    /// # #[derive(Deserialize)] struct ConfigV1 {}
+    /// # #[derive(Deserialize)] struct ConfigV2 {}
+    /// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let migrator = ConfigMigrator::new("version")
+    ///     .with_version_alias(VersionAlias::new("config_version").for_versions(1..=1));
+    ///
+    /// let (_, outcome) = migrator.migrate_config::<ConfigV2>("config_version = 1").unwrap();
+    /// assert!(outcome.migrated());
+    /// ```
+    #[must_use]
+    pub fn with_version_alias(mut self, alias: impl Into<VersionAlias<'a>>) -> Self {
+        self.version_aliases.push(alias.into());
+        self
+    }
+
+    /// Sets the format of the configs to read
+    ///
+    /// Defaults to TOML, except for [`ConfigMigrator::migrate_file`], which picks the format from the file's extension.
+    /// Configs written back by the migrator are in the same format, unless the version they end up at has a
+    /// [`Migrate::FORMAT`] of its own.
+    ///
+    /// ```
+    /// # #[cfg(feature = "json")] {
+    /// # use serde::Deserialize;
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator, Error, Format};
+    /// #[derive(Deserialize)]
+    /// struct Config {
+    ///     name: String,
+    ///     retries: Option<u8>,
+    /// }
+    ///
+    /// build_migration_chain!(Config = 1);
+    ///
+    /// let config_str = r#"{ "version": 1, "name": "MyApp", "retries": null }"#;
+    /// let (config, _) = ConfigMigrator::new("version")
+    ///     .with_format(Format::Json)
+    ///     .migrate_config::<Config>(config_str)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(config.name, "MyApp");
+    /// assert_eq!(config.retries, None);
+    ///
+    /// // Nulls can't be dropped from arrays without changing them
+    /// let result = ConfigMigrator::new("version")
+    ///     .with_format(Format::Json)
+    ///     .migrate_config::<Config>(r#"{ "version": 1, "name": "MyApp", "tags": [null] }"#);
+    /// assert!(matches!(result, Err(Error::Json(_))));
+    /// # }
+    /// ```
     #[must_use]
-    pub const fn with_default_version(mut self, default_version: i64) -> Self {
-        self.default_version = Some(default_version);
+    pub const fn with_format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Adds a default version to use if the config file doesn't contain one
+    #[must_use]
+    pub fn with_default_version(mut self, default_version: impl Into<Version>) -> Self {
+        self.default_version = Some(default_version.into());
         self
     }
 
     /// Handles the migration between versions of a configuration
     ///
-    /// On success, returns a tuple with the config and whether any migrations were performed.
-    /// Errors if it could not read the version (and no default was provided), if the version failed to match
-    /// any of the config structs, or if the config file failed to parse.
-    pub fn migrate_config<T: Migrate>(&self, config_str: &str) -> Result<(T, bool), Error> {
-        let mut doc = config_str.parse::<DocumentMut>()?;
-        let version = doc
-            .remove(self.version_key)
-            .and_then(|x| x.as_integer())
-            .or(self.default_version)
-            .ok_or(Error::NoValidVersion)?;
+    /// On success, returns a tuple with the config and a [`MigrationOutcome`] describing what was done.
+    /// Errors if the version is missing (and no default was provided) or malformed, if the version isn't part of the
+    /// migration chain, if a migration step failed, or if the config file failed to parse.
+    pub fn migrate_config<T: Migrate>(
+        &self,
+        config_str: &str,
+    ) -> Result<(T, MigrationOutcome), Error> {
+        let doc = self.input_format().parse(config_str)?;
+        self.migrate_doc(self.config_table(doc)?)
+    }
+
+    /// Handles the migration between versions of a configuration, keeping the formatting of the original file
+    ///
+    /// On success, returns a tuple with the config, a [`MigrationOutcome`], and, if any migrations were performed, the
+    /// migrated config.
+    /// Comments, key order and whitespace are kept for every value that the migration did not change, and the version
+    /// key is set to the latest version. Keys that the config ignored when it was read, like ones added by hand, are
+    /// kept as well, as long as the latest version ignores them too, except inside arrays. The migrated config is in
+    /// the format it was read in, or in the latest version's [`Migrate::FORMAT`] if it has one, in which case its
+    /// tables are written as `[table]` sections when converting to TOML. Errors in the same cases as
+    /// [`ConfigMigrator::migrate_config`], or if the migrated config could not be serialized.
+    ///
+    /// ```
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV1 {
+    ///     timeout: u32,
+    /// }
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV2 {
+    ///     timeout: u32,
+    ///     retries: u8,
+    /// }
+    ///
+    /// impl From<ConfigV1> for ConfigV2 {
+    ///     fn from(prev: ConfigV1) -> Self {
+    ///         Self { timeout: prev.timeout, retries: 4 }
+    ///     }
+    /// }
+    ///
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let config_str = "version = 1\ntimeout = 60 # seconds\n";
+    /// let (_, _, migrated) = ConfigMigrator::new("version")
+    ///     .migrate_config_preserving::<ConfigV2>(config_str)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(migrated.unwrap(), "version = 2\ntimeout = 60 # seconds\nretries = 4\n");
+    /// ```
+    pub fn migrate_config_preserving<T: Migrate + Serialize>(
+        &self,
+        config_str: &str,
+    ) -> Result<(T, MigrationOutcome, Option<String>), Error> {
+        self.migrate_preserving(config_str, self.input_format())
+    }
+
+    /// Like [`ConfigMigrator::migrate_config_preserving`], for a config in the given format
+    fn migrate_preserving<T: Migrate + Serialize>(
+        &self,
+        config_str: &str,
+        format: Format,
+    ) -> Result<(T, MigrationOutcome, Option<String>), Error> {
+        let mut doc = format.parse(config_str)?;
+
+        let (config, outcome) = self.migrate_document_preserving::<T>(&mut doc)?;
+        if !outcome.migrated() {
+            return Ok((config, outcome, None));
+        }
+
+        let migrated = format.convert(T::FORMAT.unwrap_or(format), doc)?;
+        Ok((config, outcome, Some(migrated)))
+    }
+
+    /// Format of the configs to read, when it isn't known from anywhere else
+    fn input_format(&self) -> Format {
+        self.format.unwrap_or_default()
+    }
+
+    /// Replaces the contents of the original document with the new one, keeping the formatting of unchanged values,
+    /// and sets the version key to the given version
+    ///
+    /// If the version was read from an alias, the alias is renamed to the version key where it is.
+    fn write_back<T: Migrate>(
+        &self,
+        doc: &mut DocumentMut,
+        mut new: DocumentMut,
+        version: &Version,
+    ) {
+        let version_path = path::parse(self.version_key);
+        self.rename_alias::<T>(doc, &version_path);
+        let version = Item::Value(version.to_value());
+        if path::get(doc.as_table(), &version_path).is_some() {
+            path::insert(new.as_table_mut(), &version_path, version);
+            preserve::merge_table(doc.as_table_mut(), std::mem::take(new.as_table_mut()));
+        } else {
+            preserve::merge_table(doc.as_table_mut(), std::mem::take(new.as_table_mut()));
+            path::insert(doc.as_table_mut(), &version_path, version);
+        }
+    }
+
+    /// Writes a migrated config back onto the document it was read from, like [`ConfigMigrator::write_back`], but
+    /// keeps the keys that the config ignored when it was read
+    ///
+    /// The kept keys have to be ignored by the struct of the version that is written as well, so that writing them
+    /// doesn't change what the config reads as. If that struct doesn't accept them, none are kept.
+    fn write_back_migrated<T: Migrate>(
+        &self,
+        doc: &mut DocumentMut,
+        mut new: DocumentMut,
+        version: &Version,
+        ignored: Ignored,
+    ) {
+        self.rename_alias::<T>(doc, &path::parse(self.version_key));
+
+        if !ignored.is_empty() {
+            let mut kept = new.clone();
+            for (key, item) in &ignored {
+                path::insert(kept.as_table_mut(), key, item.clone());
+            }
+
+            let mut still_ignored = Vec::new();
+            if ignored::migrate_from_doc::<T>(version, kept, Some(&mut still_ignored)).is_ok() {
+                for (key, item) in ignored {
+                    if still_ignored.iter().any(|(ignored, _)| *ignored == key) {
+                        path::insert(new.as_table_mut(), &key, item);
+                    }
+                }
+            }
+        }
+
+        self.write_back::<T>(doc, new, version);
+    }
+
+    /// Renames the alias that the version was read from to the version key, if the document has no version key
+    fn rename_alias<T: Migrate>(&self, doc: &mut DocumentMut, version_path: &[String]) {
+        if path::get(doc.as_table(), version_path).is_some() {
+            return;
+        }
+        if let Some((alias_path, _)) = self.find_alias::<T>(doc) {
+            path::rename(doc.as_table_mut(), &alias_path, version_path);
+        }
+    }
+
+    fn migrate_doc<T: Migrate>(&self, doc: DocumentMut) -> Result<(T, MigrationOutcome), Error> {
+        self.migrate_doc_ignoring(doc, None)
+    }
+
+    /// Like [`ConfigMigrator::migrate_doc`], also collecting the keys that the config ignored when it was read
+    fn migrate_doc_ignoring<T: Migrate>(
+        &self,
+        mut doc: DocumentMut,
+        ignored: Option<&mut Ignored>,
+    ) -> Result<(T, MigrationOutcome), Error> {
+        let (version, version_source) = self.take_version::<T>(&mut doc)?;
+        let config = ignored::migrate_from_doc(&version, doc, ignored)?;
+
+        let mut path: Vec<_> = T::versions().into_iter().map(|step| step.version).collect();
+        if let Some(start) = path.iter().rposition(|step| step.accepts(&version)) {
+            path.drain(..start);
+        }
+
+        let outcome = MigrationOutcome {
+            from: version,
+            to: T::VERSION,
+            path,
+            version_source,
+        };
+
+        Ok((config, outcome))
+    }
+
+    /// Removes the version key (or an alias) from the document and returns the version, falling back to the default
+    /// version
+    fn take_version<T: Migrate>(
+        &self,
+        doc: &mut DocumentMut,
+    ) -> Result<(Version, VersionSource), Error> {
+        if let Some(item) = path::remove(doc.as_table_mut(), &path::parse(self.version_key)) {
+            let version = read_version::<T>(&item).ok_or_else(|| Error::MalformedVersion {
+                found: item.to_string().trim().to_owned(),
+            })?;
+            return Ok((version, VersionSource::File));
+        }
+
+        if let Some((alias_path, version)) = self.find_alias::<T>(doc) {
+            path::remove(doc.as_table_mut(), &alias_path);
+            return Ok((version, VersionSource::File));
+        }
+
+        let default = self.default_version.clone().ok_or(Error::MissingVersion)?;
+        let version =
+            default
+                .clone()
+                .coerce(&T::VERSION)
+                .ok_or_else(|| Error::MalformedVersion {
+                    found: default.to_string(),
+                })?;
+        Ok((version, VersionSource::Default))
+    }
+
+    /// Finds the first alias that holds a version within its range, and returns its path and the version
+    fn find_alias<T: Migrate>(&self, doc: &DocumentMut) -> Option<(Vec<String>, Version)> {
+        self.version_aliases.iter().find_map(|alias| {
+            let alias_path = path::parse(alias.key);
+            let version = path::get(doc.as_table(), &alias_path).and_then(read_version::<T>)?;
+            alias
+                .versions
+                .contains(&version)
+                .then_some((alias_path, version))
+        })
+    }
+}
 
-        let config = T::migrate_from_doc(version, doc)?;
-        let migration_occured = version != T::VERSION;
+/// Reads a version from the config as the same kind of version as the chain ending in `T` uses
+fn read_version<T: Migrate>(item: &Item) -> Option<Version> {
+    item.as_value()
+        .and_then(Version::from_value)
+        .and_then(|version| version.coerce(&T::VERSION))
+}
+
+/// A fallback key to find the version of a config under
+///
+/// Can be limited to a range of versions, in which case it is ignored if the version found under it is outside of the
+/// range (or isn't a valid version at all). This is useful if the key means something else in newer configs.
+#[derive(Debug, Clone)]
+pub struct VersionAlias<'a> {
+    key: &'a str,
+    versions: (Bound<Version>, Bound<Version>),
+}
+
+impl<'a> VersionAlias<'a> {
+    /// Creates an alias for the given key, which is read in the same way as [`ConfigMigrator::new`]'s
+    #[must_use]
+    pub const fn new(key: &'a str) -> Self {
+        Self {
+            key,
+            versions: (Bound::Unbounded, Bound::Unbounded),
+        }
+    }
+
+    /// Only uses this alias for versions within the given range
+    #[must_use]
+    pub fn for_versions<V: Into<Version> + Clone>(mut self, versions: impl RangeBounds<V>) -> Self {
+        let start = versions.start_bound().cloned().map(Into::into);
+        let end = versions.end_bound().cloned().map(Into::into);
+        self.versions = (start, end);
+        self
+    }
+}
+
+impl<'a> From<&'a str> for VersionAlias<'a> {
+    fn from(key: &'a str) -> Self {
+        Self::new(key)
+    }
+}
 
-        Ok((config, migration_occured))
+/// Details about a migration performed by a [`ConfigMigrator`]
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use toml_migrate::{build_migration_chain, ConfigMigrator, Version, VersionSource};
+/// # #[derive(Deserialize)] struct ConfigV1 {}
+/// # #[derive(Deserialize)] struct ConfigV2 {}
+/// # #[derive(Deserialize)] struct ConfigV3 {}
+/// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+/// # impl From<ConfigV2> for ConfigV3 { fn from(_: ConfigV2) -> Self { Self {} } }
+/// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2, ConfigV3 = 3);
+///
+/// let (_, outcome) = ConfigMigrator::new("version")
+///     .with_default_version(1)
+///     .migrate_config::<ConfigV3>("")
+///     .unwrap();
+///
+/// assert!(outcome.migrated());
+/// assert_eq!(outcome.path, [Version::Int(1), Version::Int(2), Version::Int(3)]);
+/// assert_eq!(outcome.version_source, VersionSource::Default);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationOutcome {
+    /// Version of the config before migrating
+    pub from: Version,
+    /// Version the config was migrated to
+    pub to: Version,
+    /// Every version of the chain the config passed through, in order, starting with the one that read the config
+    /// and ending with `to`
+    pub path: Vec<Version>,
+    /// Where the original version came from
+    pub version_source: VersionSource,
+}
+
+impl MigrationOutcome {
+    /// Whether any migrations were performed
+    #[must_use]
+    pub fn migrated(&self) -> bool {
+        self.path.len() > 1
     }
 }
 
+/// Where the version of a config was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionSource {
+    /// The version was read from the config file
+    File,
+    /// The config file had no version, so the default from [`ConfigMigrator::with_default_version`] was used
+    Default,
+}
+
 /// Generates a chain connecting different config versions with the [`Migrate`] trait
 ///
 /// ```no_run
+/// # use serde::Deserialize;
+/// # use toml_migrate::build_migration_chain;
+/// # #[derive(Deserialize)] struct ConfigV1 {}
+/// # #[derive(Deserialize)] struct ConfigV2 {}
+/// # #[derive(Deserialize)] struct ConfigV3 {}
+/// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+/// # impl From<ConfigV2> for ConfigV3 { fn from(_: ConfigV2) -> Self { Self {} } }
 /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2, ConfigV3 = 3);
 /// ```
+///
+/// Versions that no longer have a struct can be migrated with functions that edit the document directly, as long as
+/// they come before all of the structs. Each function takes either a `&mut DocumentMut` or a `&mut Table`, and
+/// rewrites a document of its version into one of the next version:
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+/// use toml_migrate::toml_edit::{self, DocumentMut, Table};
+///
+/// #[derive(Deserialize)]
+/// struct Server {
+///     host: String,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct ConfigV3 {
+///     timeout: u32,
+///     server: Server,
+/// }
+///
+/// fn v1_to_v2(doc: &mut DocumentMut) {
+///     if let Some(timeout) = doc.remove("timeout_secs") {
+///         doc.insert("timeout", timeout);
+///     }
+/// }
+///
+/// fn v2_to_v3(table: &mut Table) {
+///     if let Some(host) = table.remove("host") {
+///         table.entry("server").or_insert(toml_edit::table())["host"] = host;
+///     }
+/// }
+///
+/// build_migration_chain!(1 => v1_to_v2, 2 => v2_to_v3, ConfigV3 = 3);
+///
+/// let config_str = "version = 1\ntimeout_secs = 60\nhost = \"localhost\"";
+/// let (config, _) = ConfigMigrator::new("version")
+///     .migrate_config::<ConfigV3>(config_str)
+///     .unwrap();
+///
+/// assert_eq!(config.timeout, 60);
+/// assert_eq!(config.server.host, "localhost");
+/// ```
+///
+/// Versions don't have to be integers. Strings (like `"2024-03"`) are ordered by comparing them, which works for dates,
+/// and [`SemVer`]s let compatible versions share one struct without a migration step:
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use toml_migrate::{build_migration_chain, ConfigMigrator, SemVer};
+/// # #[derive(Deserialize)] struct ConfigV1 {}
+/// # #[derive(Deserialize)] struct ConfigV2 {}
+/// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+/// build_migration_chain!(ConfigV1 = SemVer::new(1, 0, 0), ConfigV2 = SemVer::new(2, 0, 0));
+///
+/// let migrator = ConfigMigrator::new("version");
+///
+/// let (_, outcome) = migrator.migrate_config::<ConfigV2>("version = \"1.4\"").unwrap();
+/// assert!(outcome.migrated());
+///
+/// let (_, outcome) = migrator.migrate_config::<ConfigV2>("version = \"2.1\"").unwrap();
+/// assert!(!outcome.migrated());
+/// ```
+///
+/// Steps that can fail are marked with `try`, and use `TryFrom` instead of `From`. The error is returned as
+/// [`Error::Migration`]:
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use toml_migrate::{build_migration_chain, ConfigMigrator, Error, Version};
+/// # use std::time::Duration;
+/// #[derive(Deserialize)]
+/// struct ConfigV1 {
+///     timeout: i64,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct ConfigV2 {
+///     timeout: Duration,
+/// }
+///
+/// impl TryFrom<ConfigV1> for ConfigV2 {
+///     type Error = String;
+///
+///     fn try_from(prev: ConfigV1) -> Result<Self, Self::Error> {
+///         let secs = u64::try_from(prev.timeout).map_err(|_| "timeout can't be negative")?;
+///         Ok(Self { timeout: Duration::from_secs(secs) })
+///     }
+/// }
+///
+/// build_migration_chain!(ConfigV1 = 1, try ConfigV2 = 2);
+///
+/// let result = ConfigMigrator::new("version").migrate_config::<ConfigV2>("version = 1\ntimeout = -5");
+///
+/// assert!(matches!(result, Err(Error::Migration { from: Version::Int(1), to: Version::Int(2), .. })));
+/// ```
+///
+/// If the format of the config changed between versions, structs can be marked with the name of their [`Format`], like
+/// `ConfigV1: Json = 1`. Configs are then written back in the format of the latest version, as described in
+/// [`Migrate::FORMAT`].
+///
+/// The chain is checked at compile time: every version has to be newer than the one before it, or the build fails
+/// with an error naming the offending pair:
+///
+/// ```compile_fail
+/// # use serde::Deserialize;
+/// # use toml_migrate::build_migration_chain;
+/// # #[derive(Deserialize)] struct ConfigV1 {}
+/// # #[derive(Deserialize)] struct ConfigV2 {}
+/// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+/// // error: `ConfigV2` must have a newer version than `ConfigV1`, which it migrates from
+/// build_migration_chain!(ConfigV1 = 2, ConfigV2 = 1);
+/// ```
+///
+/// A struct can also only appear once. The macro doesn't check this itself, so a struct that appears twice fails the
+/// build with the compiler's error for conflicting implementations of [`Migrate`] (E0119), pointing at the macro call:
+///
+/// ```compile_fail,E0119
+/// # use serde::Deserialize;
+/// # use toml_migrate::build_migration_chain;
+/// # #[derive(Deserialize)] struct ConfigV1 {}
+/// # #[derive(Deserialize)] struct ConfigV2 {}
+/// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+/// # impl From<ConfigV2> for ConfigV1 { fn from(_: ConfigV2) -> Self { Self {} } }
+/// // error[E0119]: conflicting implementations of trait `Migrate` for type `ConfigV1`
+/// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2, ConfigV1 = 3);
+/// ```
 #[macro_export]
 macro_rules! build_migration_chain {
-    ($type:ident = $ver:literal) => {
+    ($type:ident $(: $format:ident)? = $ver:expr) => {
         impl $crate::Migrate for $type {
             type From = Self;
-            const VERSION: i64 = $ver;
+            const VERSION: $crate::Version = $crate::__private::Lit($ver).into_version();
+            const OLDEST_VERSION: $crate::Version = $crate::__private::Lit($ver).into_version();
+            $(const FORMAT: Option<$crate::Format> = Some($crate::Format::$format);)?
+
+            fn migrate_from(prev: Self) -> Result<Self, $crate::Error> {
+                Ok(prev)
+            }
         }
     };
-    ($first_type:ident = $first_ver:literal, $($rest:tt)*) => {
-        build_migration_chain!($first_type = $first_ver);
+    ($first_type:ident $(: $first_format:ident)? = $first_ver:expr, $($rest:tt)*) => {
+        build_migration_chain!($first_type $(: $first_format)? = $first_ver);
 
         build_migration_chain!(@internal $first_type, $($rest)*);
     };
-    (@internal $prev_type:ident, $type:ident = $ver:literal $(, $($rest:tt)*)?) => {
+    (@internal $prev_type:ident, try rev $type:ident $(: $format:ident)? = $ver:expr $(, $($rest:tt)*)?) => {
+        build_migration_chain!(@step $prev_type, [try] [rev] $type $(: $format)? = $ver);
+
+        $(build_migration_chain!(@internal $type, $($rest)*);)?
+    };
+    (@internal $prev_type:ident, try $type:ident $(: $format:ident)? = $ver:expr $(, $($rest:tt)*)?) => {
+        build_migration_chain!(@step $prev_type, [try] [] $type $(: $format)? = $ver);
+
+        $(build_migration_chain!(@internal $type, $($rest)*);)?
+    };
+    (@internal $prev_type:ident, rev $type:ident $(: $format:ident)? = $ver:expr $(, $($rest:tt)*)?) => {
+        build_migration_chain!(@step $prev_type, [] [rev] $type $(: $format)? = $ver);
+
+        $(build_migration_chain!(@internal $type, $($rest)*);)?
+    };
+    (@internal $prev_type:ident, $type:ident $(: $format:ident)? = $ver:expr $(, $($rest:tt)*)?) => {
+        build_migration_chain!(@step $prev_type, [] [] $type $(: $format)? = $ver);
+
+        $(build_migration_chain!(@internal $type, $($rest)*);)?
+    };
+    (@step $prev_type:ident, [$($try:ident)?] [$($rev:ident)?] $type:ident $(: $format:ident)? = $ver:expr) => {
         impl $crate::Migrate for $type {
             type From = $prev_type;
-            const VERSION: i64 = $ver;
+            const VERSION: $crate::Version = $crate::__private::Lit($ver).into_version();
+            const DOWNGRADE: Option<$crate::Downgrade<Self>> = build_migration_chain!(@downgrade $prev_type $($rev)?);
+            $(const FORMAT: Option<$crate::Format> = Some($crate::Format::$format);)?
+
+            fn migrate_from(prev: $prev_type) -> Result<Self, $crate::Error> {
+                build_migration_chain!(@migrate_from $prev_type, prev $($try)?)
+            }
         }
 
+        const _: () = assert!(
+            $crate::__private::is_newer(<$type as $crate::Migrate>::VERSION, <$prev_type as $crate::Migrate>::VERSION),
+            concat!(
+                "`", stringify!($type), "` must have a newer version than `", stringify!($prev_type),
+                "`, which it migrates from"
+            ),
+        );
+    };
+    (@migrate_from $prev_type:ident, $prev:ident try) => {
+        <Self as TryFrom<$prev_type>>::try_from($prev).map_err(|err| $crate::Error::Migration {
+            from: <$prev_type as $crate::Migrate>::VERSION,
+            to: <Self as $crate::Migrate>::VERSION,
+            source: err.into(),
+        })
+    };
+    (@migrate_from $prev_type:ident, $prev:ident) => {
+        Ok($prev.into())
+    };
+    (@downgrade $prev_type:ident rev) => {
+        Some(|config, target| $crate::__private::downgrade::<$prev_type, Self>(config, target))
+    };
+    (@downgrade $prev_type:ident) => {
+        None
+    };
+    (@doc [($first_ver:expr, $first:path, [$($first_down:path)?]) $(($doc_ver:expr, $migrate:path, [$($down:path)?]))*]
+        $type:ident $(: $format:ident)? = $ver:expr $(, $($rest:tt)*)?) => {
+        impl $crate::Migrate for $type {
+            type From = Self;
+            const VERSION: $crate::Version = $crate::__private::Lit($ver).into_version();
+            const OLDEST_VERSION: $crate::Version = $crate::__private::Lit($first_ver).into_version();
+            $(const FORMAT: Option<$crate::Format> = Some($crate::Format::$format);)?
+            const DOC_MIGRATIONS: &'static [$crate::DocMigration] = &[
+                $crate::DocMigration {
+                    version: $crate::__private::Lit($first_ver).into_version(),
+                    migrate: |doc| $first(doc),
+                    name: stringify!($first),
+                    downgrade: build_migration_chain!(@doc_downgrade $($first_down)?),
+                },
+                $($crate::DocMigration {
+                    version: $crate::__private::Lit($doc_ver).into_version(),
+                    migrate: |doc| $migrate(doc),
+                    name: stringify!($migrate),
+                    downgrade: build_migration_chain!(@doc_downgrade $($down)?),
+                }),*
+            ];
+
+            fn migrate_from(prev: Self) -> Result<Self, $crate::Error> {
+                Ok(prev)
+            }
+        }
+
+        build_migration_chain!(@check_doc $type, ($first_ver, $first) $(($doc_ver, $migrate))*);
+
         $(build_migration_chain!(@internal $type, $($rest)*);)?
     };
+    (@doc_downgrade) => {
+        None
+    };
+    (@doc_downgrade $downgrade:path) => {
+        Some(|doc| $downgrade(doc))
+    };
+    (@check_doc $type:ident, ($prev_ver:expr, $prev:path) ($next_ver:expr, $next:path) $($rest:tt)*) => {
+        const _: () = assert!(
+            $crate::__private::is_newer(
+                $crate::__private::Lit($next_ver).into_version(),
+                $crate::__private::Lit($prev_ver).into_version(),
+            ),
+            concat!(
+                "the step `", stringify!($next), "` must have a newer version than `", stringify!($prev),
+                "`, which runs before it"
+            ),
+        );
+
+        build_migration_chain!(@check_doc $type, ($next_ver, $next) $($rest)*);
+    };
+    (@check_doc $type:ident, ($prev_ver:expr, $prev:path)) => {
+        const _: () = assert!(
+            $crate::__private::is_newer(
+                <$type as $crate::Migrate>::VERSION,
+                $crate::__private::Lit($prev_ver).into_version(),
+            ),
+            concat!(
+                "`", stringify!($type), "` must have a newer version than the step `", stringify!($prev),
+                "`, which runs before it"
+            ),
+        );
+    };
+    (@doc [$($steps:tt)*] $doc_ver:expr => $migrate:path $(| $downgrade:path)?, $($rest:tt)*) => {
+        build_migration_chain!(@doc [$($steps)* ($doc_ver, $migrate, [$($downgrade)?])] $($rest)*);
+    };
+    ($doc_ver:expr => $($rest:tt)*) => {
+        build_migration_chain!(@doc [] $doc_ver => $($rest)*);
+    };
 }
 
+/// Errors that can occur while reading or migrating a config
+///
+/// Matching on it needs a wildcard arm, since some variants only exist with the feature for their format.
+///
+/// The version errors carry the version that was found along with the range the migration chain supports, so that
+/// e.g. configs written by a newer release can be told apart from broken ones:
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use toml_migrate::{build_migration_chain, ConfigMigrator, Error, Version};
+/// # #[derive(Deserialize)] struct ConfigV1 {}
+/// # #[derive(Deserialize)] struct ConfigV2 {}
+/// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+/// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+///
+/// let migrator = ConfigMigrator::new("version");
+///
+/// let result = migrator.migrate_config::<ConfigV2>("version = 3");
+/// assert!(matches!(
+///     result,
+///     Err(Error::FutureVersion { found: Version::Int(3), oldest: Version::Int(1), latest: Version::Int(2) })
+/// ));
+///
+/// let result = migrator.migrate_config::<ConfigV2>("version = \"two\"");
+/// assert!(matches!(result, Err(Error::MalformedVersion { .. })));
+/// ```
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum Error {
     /// Syntax error when parsing the TOML
     #[error("parsing error")]
@@ -109,7 +952,66 @@ pub enum Error {
     /// Error when deserializing the TOML
     #[error("deserialization error")]
     Deser(#[from] toml_edit::de::Error),
-    /// Either version field could not be read or provided version field doesn't match a valid version
-    #[error("no valid config version")]
-    NoValidVersion,
+    /// Error when reading or writing a config file
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+    /// Error when serializing the migrated config back to TOML
+    #[error("serialization error")]
+    Ser(#[from] toml_edit::ser::Error),
+    /// Error when reading or writing a JSON config
+    #[cfg(feature = "json")]
+    #[error("JSON error")]
+    Json(#[from] serde_json::Error),
+    /// Error when reading or writing a YAML config
+    #[cfg(feature = "yaml")]
+    #[error("YAML error")]
+    Yaml(#[from] serde_yaml::Error),
+    /// A fallible migration step rejected the config it was given
+    #[error("failed to migrate config from version {from} to version {to}")]
+    Migration {
+        from: Version,
+        to: Version,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The config has no version field, and no default version was provided
+    #[error("config has no version")]
+    MissingVersion,
+    /// The document has no table at the path given to [`ConfigMigrator::with_table`]
+    #[error("config has no table `{path}`")]
+    MissingTable { path: String },
+    /// The version field is not the same kind of version as the ones in the migration chain
+    #[error("config version `{found}` is not a valid version")]
+    MalformedVersion { found: String },
+    /// The version is older than the oldest version in the migration chain
+    #[error("config version {found} is older than the oldest supported version ({oldest})")]
+    OutdatedVersion {
+        found: Version,
+        oldest: Version,
+        latest: Version,
+    },
+    /// The version is within the migration chain's range, but no step of the chain has it
+    #[error("config version {found} is unknown (supported versions are {oldest} to {latest})")]
+    UnknownVersion {
+        found: Version,
+        oldest: Version,
+        latest: Version,
+    },
+    /// The version is newer than the latest version in the migration chain, so the config was probably written by a
+    /// newer release of the application
+    #[error("config version {found} is newer than the latest supported version ({latest})")]
+    FutureVersion {
+        found: Version,
+        oldest: Version,
+        latest: Version,
+    },
+    /// The config can't be migrated to the requested version, because the chain doesn't have it or it is older than
+    /// the config
+    #[error("config version {found} can't be migrated to version {target}")]
+    UnreachableTarget { found: Version, target: Version },
+    /// A step that has to be undone to downgrade the config has no downgrade
+    #[error("config version {from} can't be downgraded to version {to}")]
+    MissingDowngrade { from: Version, to: Version },
+    /// Migrating one section of a config failed, as returned by [`Sections`]
+    #[error("failed to migrate section `{section}`")]
+    Section { section: String, source: Box<Error> },
 }
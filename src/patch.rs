@@ -0,0 +1,231 @@
+//! Describing migrations as JSON Patch operations
+
+use serde::Serialize;
+use toml_edit::{ArrayOfTables, Item, Table};
+
+use crate::diff::{diff, Difference, Flattened, Path, Segment};
+use crate::{format::serialize_item, ConfigMigrator, Error, Migrate, MigrationOutcome};
+
+/// A single operation of a [JSON Patch](https://datatracker.ietf.org/doc/html/rfc6902)
+///
+/// Paths are JSON Pointers into the config, like `/server/host`. Serializing a list of these gives a JSON Patch that
+/// turns the original config into the migrated one, with tables as objects and datetimes as strings.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    /// Adds a new key
+    Add {
+        path: String,
+        #[serde(serialize_with = "serialize_item")]
+        value: Item,
+    },
+    /// Removes a key
+    Remove { path: String },
+    /// Replaces the value of a key
+    Replace {
+        path: String,
+        #[serde(serialize_with = "serialize_item")]
+        value: Item,
+    },
+    /// Moves a value to another key, without changing it
+    Move { from: String, path: String },
+}
+
+impl<'a> ConfigMigrator<'a> {
+    /// Runs the migration without writing anything, and describes the changes it would make as a JSON Patch
+    ///
+    /// The migrated config is the one [`ConfigMigrator::migrate_config_preserving`] would return, and errors are the
+    /// same as well. The patch is built from the same comparison as [`ConfigMigrator::dry_run`], so each renamed key
+    /// is a `move`, except where a table around it became a value or an array of tables. Tables that are new or gone
+    /// are added or removed as a whole, unless keys are moved into or out of them.
+    ///
+    /// ```
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV1 {
+    ///     timeout_secs: u32,
+    /// }
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV2 {
+    ///     timeout: u32,
+    ///     retries: u8,
+    /// }
+    ///
+    /// impl From<ConfigV1> for ConfigV2 {
+    ///     fn from(prev: ConfigV1) -> Self {
+    ///         Self { timeout: prev.timeout_secs, retries: 4 }
+    ///     }
+    /// }
+    ///
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let (_, patch) = ConfigMigrator::new("version")
+    ///     .migration_patch::<ConfigV2>("version = 1\ntimeout_secs = 60\n")
+    ///     .unwrap();
+    ///
+    /// assert_eq!(
+    ///     serde_json::to_string(&patch).unwrap(),
+    ///     r#"[{"op":"replace","path":"/version","value":2},{"op":"move","from":"/timeout_secs","path":"/timeout"},{"op":"add","path":"/retries","value":4}]"#,
+    /// );
+    /// ```
+    pub fn migration_patch<T: Migrate + Serialize>(
+        &self,
+        config_str: &str,
+    ) -> Result<(MigrationOutcome, Vec<PatchOperation>), Error> {
+        let (_, outcome, migrated) = self.migrate_config_preserving::<T>(config_str)?;
+        let Some(migrated) = migrated else {
+            return Ok((outcome, Vec::new()));
+        };
+
+        let format = self.input_format();
+        let original = format.parse(config_str)?;
+        let migrated = T::FORMAT.unwrap_or(format).parse(&migrated)?;
+
+        Ok((
+            outcome,
+            patch(&Flattened::new(&original), &Flattened::new(&migrated)),
+        ))
+    }
+}
+
+/// Turns the differences between two documents into operations that can be applied one after another
+///
+/// Values are replaced first, then removed values are taken out, new tables are added, renamed values are moved, and
+/// tables that are gone are removed, before the remaining values are added. A removed or added table is a single
+/// operation, unless values are moved out of or into it. A renamed value is removed and added again rather than moved
+/// if a table around it changed between a table and an array of tables or a value.
+fn patch(before: &Flattened, after: &Flattened) -> Vec<PatchOperation> {
+    let mut replaced = Vec::new();
+    let mut removed = Vec::new();
+    let mut moved = Vec::new();
+    let mut added = Vec::new();
+    for difference in diff(before, after) {
+        match difference {
+            Difference::Changed { path, to, .. } => replaced.push(PatchOperation::Replace {
+                path: pointer(&path),
+                value: Item::Value(to),
+            }),
+            Difference::Renamed { from, to, .. } if movable(before, after, &from, &to) => {
+                moved.push((from, to));
+            }
+            Difference::Renamed { from, to, value } => {
+                removed.push(from);
+                added.push((to, value));
+            }
+            Difference::Removed { path, .. } => removed.push(path),
+            Difference::Added { path, value } => added.push((path, value)),
+        }
+    }
+
+    // Tables that are gone, or that became something else, are only removed as a whole
+    let mut gone: Vec<&Path> = Vec::new();
+    for (path, _) in &before.tables {
+        if kind(after, path) != kind(before, path)
+            && !gone.iter().any(|gone| path.starts_with(gone))
+        {
+            gone.push(path);
+        }
+    }
+    // New tables are added as a whole, unless a value is moved into them
+    let mut new = Vec::new();
+    let mut whole: Vec<&Path> = Vec::new();
+    for (path, item) in &after.tables {
+        if kind(after, path) == kind(before, path)
+            || whole.iter().any(|whole| path.starts_with(whole))
+        {
+            continue;
+        }
+        let value = if moved.iter().any(|(_, to)| to.starts_with(path)) {
+            match item {
+                Item::ArrayOfTables(_) => Item::ArrayOfTables(ArrayOfTables::new()),
+                _ => Item::Table(Table::new()),
+            }
+        } else {
+            whole.push(path);
+            item.clone()
+        };
+        new.push(PatchOperation::Add {
+            path: pointer(path),
+            value,
+        });
+    }
+
+    let mut patch = replaced;
+    patch.extend(
+        removed
+            .iter()
+            .filter(|path| !gone.iter().any(|gone| path.starts_with(gone)))
+            .map(|path| PatchOperation::Remove {
+                path: pointer(path),
+            }),
+    );
+    patch.extend(new);
+    patch.extend(moved.iter().map(|(from, to)| PatchOperation::Move {
+        from: pointer(from),
+        path: pointer(to),
+    }));
+    // Tables that became something else are replaced by the operation adding it instead
+    patch.extend(
+        gone.iter()
+            .rev()
+            .filter(|path| kind(after, path).is_none())
+            .map(|path| PatchOperation::Remove {
+                path: pointer(path),
+            }),
+    );
+    patch.extend(
+        added
+            .into_iter()
+            .filter(|(path, _)| !whole.iter().any(|whole| path.starts_with(whole)))
+            .map(|(path, value)| PatchOperation::Add {
+                path: pointer(&path),
+                value: Item::Value(value),
+            }),
+    );
+    patch
+}
+
+/// What is at a path of a document
+#[derive(PartialEq)]
+enum Kind {
+    Value,
+    Table,
+    ArrayOfTables,
+}
+
+fn kind(doc: &Flattened, path: &[Segment]) -> Option<Kind> {
+    match doc.table(path) {
+        Some(Item::ArrayOfTables(_)) => Some(Kind::ArrayOfTables),
+        Some(_) => Some(Kind::Table),
+        None => doc.value(path).map(|_| Kind::Value),
+    }
+}
+
+/// Whether a renamed value can be moved, which needs the tables around both keys to stay what they were
+fn movable(before: &Flattened, after: &Flattened, from: &[Segment], to: &[Segment]) -> bool {
+    let kept = |path: &[Segment]| {
+        let (before, after) = (kind(before, path), kind(after, path));
+        before.is_none() || after.is_none() || before == after
+    };
+    kind(after, from).is_none()
+        && kind(before, to).is_none()
+        && (1..from.len()).all(|i| kept(&from[..i]))
+        && (1..to.len()).all(|i| kept(&to[..i]))
+}
+
+/// Writes a path as a JSON Pointer
+fn pointer(path: &[Segment]) -> String {
+    path.iter()
+        .map(|segment| match segment {
+            Segment::Key(key) => format!("/{}", escape(key)),
+            Segment::Index(i) => format!("/{i}"),
+        })
+        .collect()
+}
+
+/// Escapes a key for use in a JSON Pointer
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
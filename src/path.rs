@@ -0,0 +1,147 @@
+//! Version keys that point into nested tables
+
+use toml_edit::{InlineTable, Item, Key, Table, TableLike, Value};
+
+use crate::preserve;
+
+/// Splits a version key written as a TOML dotted key, like `meta.schema_version`, into the keys leading to it
+///
+/// Keys that aren't valid dotted keys are used as a single key.
+pub(crate) fn parse(key: &str) -> Vec<String> {
+    match Key::parse(key) {
+        Ok(keys) => keys.iter().map(|key| key.get().to_owned()).collect(),
+        Err(_) => vec![key.to_owned()],
+    }
+}
+
+pub(crate) fn get<'t>(table: &'t dyn TableLike, path: &[String]) -> Option<&'t Item> {
+    let (last, parents) = path.split_last()?;
+
+    let mut table = table;
+    for key in parents {
+        table = table.get(key)?.as_table_like()?;
+    }
+    table.get(last)
+}
+
+pub(crate) fn get_mut<'t>(table: &'t mut dyn TableLike, path: &[String]) -> Option<&'t mut Item> {
+    let (last, parents) = path.split_last()?;
+
+    let mut table = table;
+    for key in parents {
+        table = table.get_mut(key)?.as_table_like_mut()?;
+    }
+    table.get_mut(last)
+}
+
+/// Removes the item at the path, along with any tables that the removal left empty
+pub(crate) fn remove(table: &mut dyn TableLike, path: &[String]) -> Option<Item> {
+    match path {
+        [] => None,
+        [key] => table.remove(key),
+        [key, rest @ ..] => {
+            let child = table.get_mut(key)?.as_table_like_mut()?;
+            let item = remove(child, rest)?;
+            if child.is_empty() {
+                table.remove(key);
+            }
+            Some(item)
+        }
+    }
+}
+
+/// Sets the item at the path, creating any missing tables along the way
+///
+/// Keys that didn't exist yet are put at the top of their table, where people look for a version.
+pub(crate) fn insert(table: &mut Table, path: &[String], item: Item) {
+    match path {
+        [] => {}
+        [key] => match table.get_mut(key) {
+            Some(existing) => *existing = item,
+            None => preserve::insert_first(table, key, item),
+        },
+        [key, rest @ ..] => {
+            if !table.contains_key(key) {
+                let mut child = Table::new();
+                child.set_implicit(true);
+                preserve::insert_first(table, key, Item::Table(child));
+            }
+
+            match &mut table[key.as_str()] {
+                Item::Table(child) => insert(child, rest, item),
+                Item::Value(Value::InlineTable(child)) => insert_inline(child, rest, item),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn insert_inline(table: &mut InlineTable, path: &[String], item: Item) {
+    let Ok(value) = item.into_value() else {
+        return;
+    };
+
+    match path {
+        [] => {}
+        [key] => {
+            table.insert(key, value);
+        }
+        [key, rest @ ..] => {
+            let child = table
+                .entry(key)
+                .or_insert_with(|| InlineTable::new().into());
+            if let Value::InlineTable(child) = child {
+                insert_inline(child, rest, value.into());
+            }
+        }
+    }
+}
+
+/// Moves the item at `from` to `to` in the same table, keeping its place and the comments in front of the key
+///
+/// Returns `false` without changing anything if the paths are in different tables, or if there is nothing to move.
+pub(crate) fn rename(table: &mut dyn TableLike, from: &[String], to: &[String]) -> bool {
+    let (Some((from, parents)), Some((to, to_parents))) = (from.split_last(), to.split_last())
+    else {
+        return false;
+    };
+    if parents != to_parents {
+        return false;
+    }
+
+    let table = match parents {
+        [] => table,
+        parents => match get_mut(table, parents).and_then(Item::as_table_like_mut) {
+            Some(table) => table,
+            None => return false,
+        },
+    };
+    if table.contains_key(to) {
+        return false;
+    }
+
+    let keys: Vec<Key> = table
+        .iter()
+        .filter_map(|(key, _)| table.key(key).cloned())
+        .skip_while(|key| key.get() != from)
+        .collect();
+    let Some(first) = keys.first() else {
+        return false;
+    };
+    let renamed = Key::new(to.as_str()).with_leaf_decor(first.leaf_decor().clone());
+
+    let items: Vec<_> = keys
+        .iter()
+        .filter_map(|key| table.remove(key.get()))
+        .collect();
+    let keys = std::iter::once(&renamed).chain(keys.iter().skip(1));
+    for (key, item) in keys.zip(items) {
+        table.insert(key.get(), item);
+        // Inline tables don't take the formatting of keys on insertion
+        if let Some(mut inserted) = table.key_mut(key.get()) {
+            *inserted.leaf_decor_mut() = key.leaf_decor().clone();
+            *inserted.dotted_decor_mut() = key.dotted_decor().clone();
+        }
+    }
+    true
+}
@@ -0,0 +1,143 @@
+//! Format-preserving merging of a freshly serialized config onto the original document
+
+use toml_edit::{ArrayOfTables, DocumentMut, InlineTable, Item, Table, Value};
+
+/// Updates `original` so that it holds the same data as `new`
+///
+/// Keys that exist in both tables keep their position and formatting, and values that are equal keep their exact
+/// representation (including comments). Keys only in `new` are appended, and keys only in `original` are removed.
+pub(crate) fn merge_table(original: &mut Table, new: Table) {
+    original.retain(|key, _| new.contains_key(key));
+
+    for (key, item) in new {
+        match original.get_mut(&key) {
+            Some(existing) => merge_item(existing, item),
+            None => {
+                original.insert(&key, expand(item));
+            }
+        }
+    }
+}
+
+fn merge_inline_table(original: &mut InlineTable, new: InlineTable) {
+    original.retain(|key, _| new.contains_key(key));
+
+    for (key, value) in new {
+        match original.get_mut(&key) {
+            Some(existing) => merge_value(existing, value),
+            None => {
+                original.insert(key, value);
+            }
+        }
+    }
+}
+
+fn merge_item(original: &mut Item, new: Item) {
+    match (original, new) {
+        (Item::Table(original), Item::Value(Value::InlineTable(new))) => {
+            merge_table(original, new.into_table());
+        }
+        (Item::Table(original), Item::Table(new)) => merge_table(original, new),
+        (Item::Value(Value::InlineTable(original)), Item::Table(new)) => {
+            merge_inline_table(original, new.into_inline_table());
+        }
+        (Item::ArrayOfTables(original), Item::Value(Value::Array(new)))
+            if original.len() == new.len() && new.iter().all(Value::is_inline_table) =>
+        {
+            for (original, new) in original.iter_mut().zip(new) {
+                if let Value::InlineTable(new) = new {
+                    merge_table(original, new.into_table());
+                }
+            }
+        }
+        (Item::Value(original), Item::Value(new)) => merge_value(original, new),
+        (original, new) => *original = expand(new),
+    }
+}
+
+fn merge_value(original: &mut Value, new: Value) {
+    match (original, new) {
+        (Value::InlineTable(original), Value::InlineTable(new)) => {
+            merge_inline_table(original, new)
+        }
+        (Value::Array(original), Value::Array(new)) if original.len() == new.len() => {
+            for (original, new) in original.iter_mut().zip(new) {
+                merge_value(original, new);
+            }
+        }
+        (original, new) => {
+            if !scalar_eq(original, &new) {
+                let decor = original.decor().clone();
+                *original = new;
+                *original.decor_mut() = decor;
+            }
+        }
+    }
+}
+
+fn scalar_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.value() == b.value(),
+        (Value::Integer(a), Value::Integer(b)) => a.value() == b.value(),
+        (Value::Float(a), Value::Float(b)) => a.value() == b.value(),
+        (Value::Boolean(a), Value::Boolean(b)) => a.value() == b.value(),
+        (Value::Datetime(a), Value::Datetime(b)) => a.value() == b.value(),
+        _ => false,
+    }
+}
+
+/// Turns the inline tables produced by the serializer into standard tables, so new sections read like hand-written TOML
+fn expand(item: Item) -> Item {
+    match item {
+        Item::Value(Value::InlineTable(table)) => Item::Table(expand_table(table)),
+        Item::Value(Value::Array(array))
+            if !array.is_empty() && array.iter().all(Value::is_inline_table) =>
+        {
+            let mut tables = ArrayOfTables::new();
+            for value in array {
+                if let Value::InlineTable(table) = value {
+                    tables.push(expand_table(table));
+                }
+            }
+            Item::ArrayOfTables(tables)
+        }
+        item => item,
+    }
+}
+
+/// Turns the inline tables of a whole document into standard tables, for documents read from another format where
+/// every table is inline
+pub(crate) fn expand_document(doc: &mut DocumentMut) {
+    for (_, item) in doc.as_table_mut().iter_mut() {
+        *item = expand(std::mem::take(item));
+    }
+}
+
+fn expand_table(table: InlineTable) -> Table {
+    let mut table = table.into_table();
+    for (_, item) in table.iter_mut() {
+        *item = expand(std::mem::take(item));
+    }
+    table
+}
+
+/// Inserts a value at the top of a table, taking over any comment that sat above the previous first key
+pub(crate) fn insert_first(table: &mut Table, key: &str, item: Item) {
+    let first = table
+        .iter()
+        .find(|(_, item)| item.is_value())
+        .map(|(key, _)| key.to_owned());
+    table.insert(key, item);
+    table.sort_values_by(|a, _, b, _| (b.get() == key).cmp(&(a.get() == key)));
+
+    let Some(mut first) = first.and_then(|first| table.key_mut(&first)) else {
+        return;
+    };
+    let Some(prefix) = first.leaf_decor().prefix().cloned() else {
+        return;
+    };
+    first.leaf_decor_mut().set_prefix("");
+    if let Some(mut key) = table.key_mut(key) {
+        key.leaf_decor_mut().set_prefix(prefix);
+    }
+}
@@ -0,0 +1,193 @@
+//! Configs made of independently versioned sections
+
+use serde::Serialize;
+use toml_edit::{DocumentMut, Key};
+
+use crate::{path, table, ConfigMigrator, Error, Format, Migrate, MigrationOutcome};
+
+impl<'a> ConfigMigrator<'a> {
+    /// Reads a config made of sections that each have their own version and migration chain
+    ///
+    /// The sections are then migrated one by one with [`Sections::migrate`] and [`Sections::migrate_each`], and the
+    /// results can be put together into one struct. Errors if the config failed to parse.
+    ///
+    /// ```
+    /// # use std::collections::BTreeMap;
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator, Error};
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ServerV1 {
+    ///     port: u16,
+    /// }
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ServerV2 {
+    ///     listen: String,
+    /// }
+    ///
+    /// impl From<ServerV1> for ServerV2 {
+    ///     fn from(prev: ServerV1) -> Self {
+    ///         Self { listen: format!("0.0.0.0:{}", prev.port) }
+    ///     }
+    /// }
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct Storage {
+    ///     path: String,
+    /// }
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct Plugin {
+    ///     enabled: bool,
+    /// }
+    ///
+    /// build_migration_chain!(ServerV1 = 1, ServerV2 = 2);
+    /// build_migration_chain!(Storage = 5);
+    /// build_migration_chain!(Plugin = 1);
+    ///
+    /// struct Config {
+    ///     server: ServerV2,
+    ///     storage: Storage,
+    ///     plugins: BTreeMap<String, Plugin>,
+    /// }
+    ///
+    /// let config_str = "\
+    /// [server]
+    /// version = 1
+    /// port = 8080
+    ///
+    /// [storage]
+    /// version = 5
+    /// path = \"/var/lib/app\"
+    ///
+    /// [plugins.metrics]
+    /// version = 1
+    /// enabled = true
+    /// ";
+    ///
+    /// let migrator = ConfigMigrator::new("version");
+    /// let mut sections = migrator.sections(config_str).unwrap();
+    /// let config = Config {
+    ///     server: sections.migrate("server").unwrap(),
+    ///     storage: sections.migrate("storage").unwrap(),
+    ///     plugins: sections.migrate_each("plugins").unwrap(),
+    /// };
+    ///
+    /// assert_eq!(config.server.listen, "0.0.0.0:8080");
+    /// assert!(config.plugins["metrics"].enabled);
+    /// assert_eq!(sections.outcomes().len(), 3);
+    ///
+    /// let migrated = sections.finish().unwrap();
+    /// assert!(migrated.unwrap().starts_with("[server]\nversion = 2\nlisten = \"0.0.0.0:8080\"\n"));
+    ///
+    /// // Errors say which section they came from
+    /// let mut sections = migrator.sections("[server]\nversion = 3\nlisten = \"::\"\n").unwrap();
+    /// let result = sections.migrate::<ServerV2>("server");
+    /// assert!(matches!(result, Err(Error::Section { section, .. }) if section == "server"));
+    /// ```
+    pub fn sections(&self, config_str: &str) -> Result<Sections<'_>, Error> {
+        let format = self.input_format();
+        Ok(Sections {
+            migrator: self,
+            doc: format.parse(config_str)?,
+            format,
+            outcomes: Vec::new(),
+        })
+    }
+}
+
+/// A config whose sections are versioned and migrated independently, as returned by [`ConfigMigrator::sections`]
+///
+/// Each section is a table with the migrator's version key inside it. Section paths are read as TOML dotted keys from
+/// the root of the document, like `server` or `plugins.metrics`.
+pub struct Sections<'m> {
+    migrator: &'m ConfigMigrator<'m>,
+    doc: DocumentMut,
+    format: Format,
+    outcomes: Vec<(String, MigrationOutcome)>,
+}
+
+impl Sections<'_> {
+    /// Migrates the section at the given path with the chain ending in `T`
+    ///
+    /// Errors in the same cases as [`ConfigMigrator::migrate_config_preserving`], or with [`Error::MissingTable`] if
+    /// there is no such section. The error is wrapped in an [`Error::Section`] naming the section.
+    pub fn migrate<T: Migrate + Serialize>(&mut self, section: &str) -> Result<T, Error> {
+        self.migrate_section(section)
+            .map_err(|err| section_error(section, err))
+    }
+
+    /// Migrates every table under the given path as a section of its own, with the chain ending in `T`
+    ///
+    /// This is meant for sections like `[plugins.*]`, and returns the migrated sections by key, in any collection like
+    /// a `HashMap`. If there is nothing at the path, there are no sections. Errors like [`Sections::migrate`] for the
+    /// first section that fails.
+    pub fn migrate_each<T, C>(&mut self, path: &str) -> Result<C, Error>
+    where
+        T: Migrate + Serialize,
+        C: FromIterator<(String, T)>,
+    {
+        let keys: Vec<_> = match path::get(self.doc.as_table(), &path::parse(path)) {
+            Some(item) => {
+                let table = item.as_table_like().ok_or_else(|| {
+                    let err = Error::MissingTable {
+                        path: path.to_owned(),
+                    };
+                    section_error(path, err)
+                })?;
+                table.iter().map(|(key, _)| key.to_owned()).collect()
+            }
+            None => Vec::new(),
+        };
+
+        keys.into_iter()
+            .map(|key| {
+                let section = format!("{path}.{}", Key::new(key.as_str()));
+                self.migrate(&section).map(|config| (key, config))
+            })
+            .collect()
+    }
+
+    /// The outcome of every section migrated so far, by path
+    #[must_use]
+    pub fn outcomes(&self) -> &[(String, MigrationOutcome)] {
+        &self.outcomes
+    }
+
+    /// Returns the whole migrated config, if any of the sections were migrated
+    ///
+    /// Sections keep their comments and formatting like with [`ConfigMigrator::migrate_config_preserving`], and
+    /// everything outside of them is left as it was. Errors if the migrated config could not be written.
+    pub fn finish(self) -> Result<Option<String>, Error> {
+        if self.outcomes.iter().any(|(_, outcome)| outcome.migrated()) {
+            Ok(Some(self.format.render(&self.doc)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn migrate_section<T: Migrate + Serialize>(&mut self, section: &str) -> Result<T, Error> {
+        let mut table = table::get_table(&self.doc, section)?;
+        let mut ignored = Vec::new();
+        let (config, outcome) = self
+            .migrator
+            .migrate_doc_ignoring::<T>(table.clone(), Some(&mut ignored))?;
+
+        if outcome.migrated() {
+            let new = toml_edit::ser::to_document(&config)?;
+            self.migrator
+                .write_back_migrated::<T>(&mut table, new, &T::VERSION, ignored);
+            table::set_table(&mut self.doc, section, table);
+        }
+
+        self.outcomes.push((section.to_owned(), outcome));
+        Ok(config)
+    }
+}
+
+fn section_error(section: &str, err: Error) -> Error {
+    Error::Section {
+        section: section.to_owned(),
+        source: Box::new(err),
+    }
+}
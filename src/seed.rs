@@ -0,0 +1,78 @@
+//! Migrating configs while deserializing them with any serde deserializer
+
+use std::marker::PhantomData;
+
+use serde::{
+    de::{self, DeserializeSeed},
+    Deserialize, Deserializer,
+};
+
+use crate::{format::de::RootTable, ConfigMigrator, Migrate, MigrationOutcome};
+
+impl<'a> ConfigMigrator<'a> {
+    /// Returns a [`DeserializeSeed`] that migrates the config it deserializes to `T`, for configs that don't come
+    /// from a string
+    ///
+    /// This works with any self-describing deserializer, like one for a config that is part of a bigger document or
+    /// that was loaded by another crate. The version is read and removed in the same way as for
+    /// [`ConfigMigrator::migrate_config`], and its errors are turned into errors of the deserializer.
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// # use serde::de::DeserializeSeed;
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// #[derive(Deserialize)]
+    /// struct ConfigV1 {
+    ///     timeout_secs: u32,
+    /// }
+    ///
+    /// #[derive(Deserialize)]
+    /// struct ConfigV2 {
+    ///     timeout: u32,
+    /// }
+    ///
+    /// impl From<ConfigV1> for ConfigV2 {
+    ///     fn from(prev: ConfigV1) -> Self {
+    ///         Self { timeout: prev.timeout_secs }
+    ///     }
+    /// }
+    ///
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let mut deserializer = serde_json::Deserializer::from_str(r#"{ "version": 1, "timeout_secs": 60 }"#);
+    /// let (config, outcome) = ConfigMigrator::new("version")
+    ///     .seed::<ConfigV2>()
+    ///     .deserialize(&mut deserializer)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(config.timeout, 60);
+    /// assert!(outcome.migrated());
+    /// ```
+    #[must_use]
+    pub fn seed<T: Migrate>(&self) -> MigrateSeed<'_, T> {
+        MigrateSeed {
+            migrator: self,
+            config: PhantomData,
+        }
+    }
+}
+
+/// Migrates a config to `T` while deserializing it, as returned by [`ConfigMigrator::seed`]
+///
+/// Deserializes into the config and a [`MigrationOutcome`], like [`ConfigMigrator::migrate_config`] returns.
+pub struct MigrateSeed<'m, T> {
+    migrator: &'m ConfigMigrator<'m>,
+    config: PhantomData<fn() -> T>,
+}
+
+impl<'de, T: Migrate> DeserializeSeed<'de> for MigrateSeed<'_, T> {
+    type Value = (T, MigrationOutcome);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let RootTable(doc) = RootTable::deserialize(deserializer)?;
+        self.migrator
+            .config_table(doc)
+            .and_then(|doc| self.migrator.migrate_doc(doc))
+            .map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,88 @@
+//! Writing configs back to TOML
+
+use serde::Serialize;
+use toml_edit::{DocumentMut, Item};
+
+use crate::{path, ConfigMigrator, Error, Migrate};
+
+impl<'a> ConfigMigrator<'a> {
+    /// Serializes a config in the format of its version, with the version key set to `T::VERSION` and placed first
+    ///
+    /// The format is [`Migrate::FORMAT`] if the version has one, and the migrator's [`Format`](crate::Format)
+    /// otherwise.
+    ///
+    /// ```
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// #[derive(Deserialize, Serialize)]
+    /// struct Config {
+    ///     name: String,
+    /// }
+    ///
+    /// build_migration_chain!(Config = 2);
+    ///
+    /// let config = Config { name: "MyApp".to_owned() };
+    /// let config_str = ConfigMigrator::new("version").serialize_config(&config).unwrap();
+    ///
+    /// assert_eq!(config_str, "version = 2\nname = \"MyApp\"\n");
+    /// ```
+    pub fn serialize_config<T: Migrate + Serialize>(&self, config: &T) -> Result<String, Error> {
+        T::FORMAT
+            .unwrap_or(self.input_format())
+            .render(&self.serialize_config_to_document(config)?)
+    }
+
+    /// Serializes a config to a document, with the version key set to `T::VERSION` and placed first
+    pub fn serialize_config_to_document<T: Migrate + Serialize>(
+        &self,
+        config: &T,
+    ) -> Result<DocumentMut, Error> {
+        let mut doc = toml_edit::ser::to_document(config)?;
+        let version_path = path::parse(self.version_key);
+        path::insert(
+            doc.as_table_mut(),
+            &version_path,
+            Item::Value(T::VERSION.to_value()),
+        );
+        Ok(doc)
+    }
+
+    /// Serializes a config onto an existing document, such as the one the config was read from
+    ///
+    /// Values that didn't change keep their comments and formatting, and keys that the config doesn't have are
+    /// removed. The version key is set to `T::VERSION`, in its existing place if the document already has one. If a
+    /// table was set with [`ConfigMigrator::with_table`], the config is written onto that table and the rest of the
+    /// document is left alone, erroring with [`Error::MissingTable`] if the document has no table there.
+    ///
+    /// ```
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// # use toml_migrate::toml_edit::DocumentMut;
+    /// #[derive(Deserialize, Serialize)]
+    /// struct Config {
+    ///     name: String,
+    ///     timeout: u32,
+    /// }
+    ///
+    /// build_migration_chain!(Config = 2);
+    ///
+    /// let mut doc: DocumentMut = "# My config\nversion = 2\nname = \"MyApp\"\ntimeout = 60 # seconds\n"
+    ///     .parse()
+    ///     .unwrap();
+    /// let config = Config { name: "MyApp".to_owned(), timeout: 30 };
+    /// ConfigMigrator::new("version").serialize_config_onto(&config, &mut doc).unwrap();
+    ///
+    /// assert_eq!(doc.to_string(), "# My config\nversion = 2\nname = \"MyApp\"\ntimeout = 30 # seconds\n");
+    /// ```
+    pub fn serialize_config_onto<T: Migrate + Serialize>(
+        &self,
+        config: &T,
+        doc: &mut DocumentMut,
+    ) -> Result<(), Error> {
+        let mut table = self.config_table(doc.clone())?;
+        let new = toml_edit::ser::to_document(config)?;
+        self.write_back::<T>(&mut table, new, &T::VERSION);
+        self.replace_config_table(doc, table);
+        Ok(())
+    }
+}
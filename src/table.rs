@@ -0,0 +1,104 @@
+//! Migrating configs that live in a table of a larger document
+
+use toml_edit::{DocumentMut, Item, Value};
+
+use crate::{path, ConfigMigrator, Error};
+
+impl<'a> ConfigMigrator<'a> {
+    /// Only migrates the table at the given path, for configs kept inside another tool's file, like `[tool.my-tool]` in
+    /// `pyproject.toml` or `[package.metadata.my-tool]` in `Cargo.toml`
+    ///
+    /// The path is read as a TOML dotted key, like the version key, which is then looked up inside the table. When the
+    /// config is written back, only the table changes, and the rest of the document is kept as it was. Errors with
+    /// [`Error::MissingTable`] if the document has no table at the path.
+    ///
+    /// ```
+    /// # use serde::{Deserialize, Serialize};
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator};
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV1 {
+    ///     line_length: u32,
+    /// }
+    ///
+    /// #[derive(Deserialize, Serialize)]
+    /// struct ConfigV2 {
+    ///     max_line_length: u32,
+    /// }
+    ///
+    /// impl From<ConfigV1> for ConfigV2 {
+    ///     fn from(prev: ConfigV1) -> Self {
+    ///         Self { max_line_length: prev.line_length }
+    ///     }
+    /// }
+    ///
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+    ///
+    /// let config_str = "\
+    /// [project]
+    /// name = \"my-project\" # not ours
+    ///
+    /// [tool.my-tool]
+    /// version = 1
+    /// line_length = 100
+    ///
+    /// [tool.other-tool]
+    /// line_length = 80
+    /// ";
+    ///
+    /// let (_, _, migrated) = ConfigMigrator::new("version")
+    ///     .with_table("tool.my-tool")
+    ///     .migrate_config_preserving::<ConfigV2>(config_str)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(
+    ///     migrated.unwrap(),
+    ///     config_str.replace("version = 1\nline_length", "version = 2\nmax_line_length"),
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn with_table(mut self, table: &'a str) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    /// Takes the config out of the document it was read from, which is the whole document unless a table was set
+    pub(crate) fn config_table(&self, doc: DocumentMut) -> Result<DocumentMut, Error> {
+        match self.table {
+            Some(table) => get_table(&doc, table),
+            None => Ok(doc),
+        }
+    }
+
+    /// Puts a config taken out with [`ConfigMigrator::config_table`] back into the document, in the same place
+    pub(crate) fn replace_config_table(&self, doc: &mut DocumentMut, config: DocumentMut) {
+        match self.table {
+            Some(table) => set_table(doc, table, config),
+            None => *doc = config,
+        }
+    }
+}
+
+/// Copies the table at the path out of the document, as a document of its own
+pub(crate) fn get_table(doc: &DocumentMut, table: &str) -> Result<DocumentMut, Error> {
+    path::get(doc.as_table(), &path::parse(table))
+        .and_then(|item| item.clone().into_table().ok())
+        .map(DocumentMut::from)
+        .ok_or_else(|| Error::MissingTable {
+            path: table.to_owned(),
+        })
+}
+
+/// Replaces the table at the path with the given document, keeping the formatting around the table
+pub(crate) fn set_table(doc: &mut DocumentMut, table: &str, mut config: DocumentMut) {
+    let config = std::mem::take(config.as_table_mut());
+    if let Some(item) = path::get_mut(doc.as_table_mut(), &path::parse(table)) {
+        *item = match item {
+            Item::Value(value) => {
+                let mut config = config.into_inline_table();
+                *config.decor_mut() = value.decor().clone();
+                Item::Value(Value::InlineTable(config))
+            }
+            _ => Item::Table(config),
+        };
+    }
+}
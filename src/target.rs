@@ -0,0 +1,168 @@
+//! Migrating configs to a version other than the latest one
+
+use std::any::{Any, TypeId};
+
+use toml_edit::{DocumentMut, Item};
+
+use crate::{path, version_error, ConfigMigrator, Error, Migrate, MigrationOutcome, Version};
+
+/// A config migrated to some version of a chain, as returned by [`ConfigMigrator::migrate_config_to`]
+#[derive(Debug)]
+pub enum Intermediate {
+    /// The config as the struct declared for the target version
+    Config(Box<dyn Any>),
+    /// The document, for target versions that only have a document-level step
+    ///
+    /// The version key is set to the target version, so the document can be written back as is.
+    Document(DocumentMut),
+}
+
+impl Intermediate {
+    /// Returns the config if the target version was declared with the struct `U`
+    #[must_use]
+    pub fn downcast<U: Migrate>(self) -> Option<U> {
+        match self {
+            Self::Config(config) => config.downcast().ok().map(|config| *config),
+            Self::Document(_) => None,
+        }
+    }
+
+    /// Returns the document if the target version has a document-level step
+    #[must_use]
+    pub fn into_document(self) -> Option<DocumentMut> {
+        match self {
+            Self::Config(_) => None,
+            Self::Document(doc) => Some(doc),
+        }
+    }
+}
+
+impl<'a> ConfigMigrator<'a> {
+    /// Migrates a config along the chain ending in `T`, but stops at the given version instead of the latest one
+    ///
+    /// This is useful to write a config that an older release can still read. Errors in the same cases as
+    /// [`ConfigMigrator::migrate_config`], or with [`Error::UnreachableTarget`] if the target isn't part of the chain or
+    /// is older than the config.
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// # use toml_migrate::{build_migration_chain, ConfigMigrator, Version};
+    /// #[derive(Deserialize)]
+    /// struct ConfigV1 {
+    ///     timeout: u32,
+    /// }
+    ///
+    /// #[derive(Deserialize)]
+    /// struct ConfigV2 {
+    ///     timeout: u32,
+    ///     retries: u8,
+    /// }
+    ///
+    /// # #[derive(Deserialize)] struct ConfigV3 {}
+    /// # impl From<ConfigV2> for ConfigV3 { fn from(_: ConfigV2) -> Self { Self {} } }
+    /// impl From<ConfigV1> for ConfigV2 {
+    ///     fn from(prev: ConfigV1) -> Self {
+    ///         Self { timeout: prev.timeout, retries: 4 }
+    ///     }
+    /// }
+    ///
+    /// build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2, ConfigV3 = 3);
+    ///
+    /// let (config, outcome) = ConfigMigrator::new("version")
+    ///     .migrate_config_to::<ConfigV3>("version = 1\ntimeout = 60", 2)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(outcome.to, Version::Int(2));
+    /// assert_eq!(config.downcast::<ConfigV2>().unwrap().retries, 4);
+    /// ```
+    pub fn migrate_config_to<T: Migrate>(
+        &self,
+        config_str: &str,
+        target: impl Into<Version>,
+    ) -> Result<(Intermediate, MigrationOutcome), Error> {
+        let mut doc = self.config_table(self.input_format().parse(config_str)?)?;
+        let (version, version_source) = self.take_version::<T>(&mut doc)?;
+
+        let target = target.into();
+        let target = target
+            .clone()
+            .coerce(&T::VERSION)
+            .ok_or_else(|| Error::MalformedVersion {
+                found: target.to_string(),
+            })?;
+
+        let chain = T::versions();
+        let start = chain
+            .iter()
+            .rposition(|step| step.version.accepts(&version))
+            .ok_or_else(|| version_error::<T>(version.clone()))?;
+        let end = chain
+            .iter()
+            .rposition(|step| step.version.accepts(&target))
+            .filter(|&end| end >= start)
+            .ok_or_else(|| Error::UnreachableTarget {
+                found: version.clone(),
+                target,
+            })?;
+
+        let path: Vec<_> = chain[start..=end]
+            .iter()
+            .map(|step| step.version.clone())
+            .collect();
+        let target = path[path.len() - 1].clone();
+
+        let mut migrated = migrate_to::<T>(&version, doc, &target)?;
+        if let Intermediate::Document(doc) = &mut migrated {
+            let version_path = path::parse(self.version_key);
+            path::insert(
+                doc.as_table_mut(),
+                &version_path,
+                Item::Value(target.to_value()),
+            );
+        }
+
+        let outcome = MigrationOutcome {
+            from: version,
+            to: target,
+            path,
+            version_source,
+        };
+
+        Ok((migrated, outcome))
+    }
+}
+
+/// Walks down the chain ending in `T` to the step declared with the target version, and migrates the config to it
+fn migrate_to<T: Migrate>(
+    version: &Version,
+    mut doc: DocumentMut,
+    target: &Version,
+) -> Result<Intermediate, Error> {
+    if T::VERSION == *target {
+        let config = T::migrate_from_doc(version, doc)?;
+        Ok(Intermediate::Config(Box::new(config)))
+    } else if TypeId::of::<T>() == TypeId::of::<T::From>() {
+        let unreachable = || Error::UnreachableTarget {
+            found: version.clone(),
+            target: target.clone(),
+        };
+        let steps = T::DOC_MIGRATIONS;
+        let start = steps
+            .iter()
+            .rposition(|step| step.version.accepts(version))
+            .ok_or_else(unreachable)?;
+        let end = steps
+            .iter()
+            .position(|step| step.version == *target)
+            .filter(|&end| end >= start)
+            .ok_or_else(unreachable)?;
+
+        for step in &steps[start..end] {
+            (step.migrate)(&mut doc);
+        }
+
+        Ok(Intermediate::Document(doc))
+    } else {
+        migrate_to::<T::From>(version, doc, target)
+    }
+}
@@ -0,0 +1,246 @@
+//! Identifiers for config versions
+
+use std::{borrow::Cow, cmp::Ordering, fmt, str::FromStr};
+
+use toml_edit::Value;
+
+/// Identifier of a config version
+///
+/// Every version in a migration chain should be of the same kind. Versions of different kinds can't be compared.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Version {
+    /// Integer version, like `version = 3`
+    Int(i64),
+    /// Semantic version, like `version = "1.2"`
+    ///
+    /// A struct declared with a semantic version also reads every later version that is compatible with it, using the
+    /// same rules as Cargo: `1.2` reads `1.2.1` and `1.5`, but not `2.0`, and `0.2` reads `0.2.3`, but not `0.3`.
+    /// This lets compatible versions share a struct without a migration step.
+    SemVer(SemVer),
+    /// Any other string, like `version = "2024-03"`
+    ///
+    /// These are ordered by comparing the strings, which works for dates written as `YYYY-MM` or `YYYY-MM-DD`.
+    Str(Cow<'static, str>),
+}
+
+impl Version {
+    /// Whether a config of the `found` version can be read by the struct or step declared with this version
+    #[must_use]
+    pub fn accepts(&self, found: &Version) -> bool {
+        match (self, found) {
+            (Self::SemVer(declared), Self::SemVer(found)) => declared.is_compatible(found),
+            (declared, found) => declared == found,
+        }
+    }
+
+    /// Converts the version to the TOML value written into config files
+    #[must_use]
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Int(version) => Value::from(*version),
+            Self::SemVer(version) => Value::from(version.to_string()),
+            Self::Str(version) => Value::from(version.as_ref()),
+        }
+    }
+
+    pub(crate) fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(version) => Some(Self::Int(*version.value())),
+            Value::String(version) => Some(Self::Str(Cow::Owned(version.value().clone()))),
+            _ => None,
+        }
+    }
+
+    /// Interprets a version read from a file (or given as a default) as the same kind of version as `like`
+    pub(crate) fn coerce(self, like: &Version) -> Option<Self> {
+        match (self, like) {
+            (Self::Int(version), Self::SemVer(_)) => {
+                let major = u64::try_from(version).ok()?;
+                Some(Self::SemVer(SemVer::new(major, 0, 0)))
+            }
+            (Self::Str(version), Self::SemVer(_)) => version.parse().ok().map(Self::SemVer),
+            (version @ Self::Int(_), Self::Int(_))
+            | (version @ Self::SemVer(_), Self::SemVer(_))
+            | (version @ Self::Str(_), Self::Str(_)) => Some(version),
+            _ => None,
+        }
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a.partial_cmp(b),
+            (Self::SemVer(a), Self::SemVer(b)) => a.partial_cmp(b),
+            (Self::Str(a), Self::Str(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(version) => version.fmt(f),
+            Self::SemVer(version) => version.fmt(f),
+            Self::Str(version) => version.fmt(f),
+        }
+    }
+}
+
+impl From<i64> for Version {
+    fn from(version: i64) -> Self {
+        Self::Int(version)
+    }
+}
+
+impl From<i32> for Version {
+    fn from(version: i32) -> Self {
+        Self::Int(version.into())
+    }
+}
+
+impl From<SemVer> for Version {
+    fn from(version: SemVer) -> Self {
+        Self::SemVer(version)
+    }
+}
+
+impl From<&'static str> for Version {
+    fn from(version: &'static str) -> Self {
+        Self::Str(Cow::Borrowed(version))
+    }
+}
+
+impl From<String> for Version {
+    fn from(version: String) -> Self {
+        Self::Str(Cow::Owned(version))
+    }
+}
+
+/// A semantic version made of a major, minor and patch number
+///
+/// Pre-release and build metadata are not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SemVer {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl SemVer {
+    #[must_use]
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Whether `other` is a later version that can be read in the same way as this one
+    #[must_use]
+    pub const fn is_compatible(&self, other: &SemVer) -> bool {
+        let same_series = if self.major == 0 {
+            other.major == 0 && self.minor == other.minor
+        } else {
+            self.major == other.major
+        };
+
+        same_series
+            && (other.minor > self.minor
+                || (other.minor == self.minor && other.patch >= self.patch))
+    }
+}
+
+impl fmt::Display for SemVer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Error when parsing a [`SemVer`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid semantic version")]
+pub struct ParseSemVerError;
+
+impl FromStr for SemVer {
+    type Err = ParseSemVerError;
+
+    /// Parses versions like `1`, `1.2` or `1.2.3`, with any missing parts set to 0
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('.').map(|part| {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(ParseSemVerError);
+            }
+            part.parse::<u64>().map_err(|_| ParseSemVerError)
+        });
+
+        let major = parts.next().ok_or(ParseSemVerError)??;
+        let minor = parts.next().transpose()?.unwrap_or(0);
+        let patch = parts.next().transpose()?.unwrap_or(0);
+        if parts.next().is_some() {
+            return Err(ParseSemVerError);
+        }
+
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+/// Turns the version written in a [`build_migration_chain!`](crate::build_migration_chain) call into a [`Version`]
+///
+/// Each supported type gets its own `into_version`, so the macro doesn't need to know what kind of version it was
+/// given.
+pub struct Lit<T>(pub T);
+
+impl Lit<i64> {
+    pub const fn into_version(self) -> Version {
+        Version::Int(self.0)
+    }
+}
+
+impl Lit<&'static str> {
+    pub const fn into_version(self) -> Version {
+        Version::Str(Cow::Borrowed(self.0))
+    }
+}
+
+impl Lit<SemVer> {
+    pub const fn into_version(self) -> Version {
+        Version::SemVer(self.0)
+    }
+}
+
+/// Whether `next` is a later version of the same kind as `prev`, for checking migration chains at compile time
+///
+/// Takes the versions by value because they can't be dropped in a const context.
+pub const fn is_newer(next: Version, prev: Version) -> bool {
+    let newer = match (&next, &prev) {
+        (Version::Int(next), Version::Int(prev)) => *next > *prev,
+        (Version::SemVer(next), Version::SemVer(prev)) => {
+            next.major > prev.major
+                || (next.major == prev.major
+                    && (next.minor > prev.minor
+                        || (next.minor == prev.minor && next.patch > prev.patch)))
+        }
+        (Version::Str(Cow::Borrowed(next)), Version::Str(Cow::Borrowed(prev))) => {
+            str_greater(next.as_bytes(), prev.as_bytes())
+        }
+        _ => false,
+    };
+
+    std::mem::forget(next);
+    std::mem::forget(prev);
+    newer
+}
+
+const fn str_greater(a: &[u8], b: &[u8]) -> bool {
+    let mut i = 0;
+    while i < a.len() && i < b.len() {
+        if a[i] != b[i] {
+            return a[i] > b[i];
+        }
+        i += 1;
+    }
+    a.len() > b.len()
+}
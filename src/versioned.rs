@@ -0,0 +1,195 @@
+//! Items of a config that carry their own version
+
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+use toml_edit::Item;
+
+use crate::{
+    format::{de::RootTable, serialize_item_as_toml},
+    path, read_version, Error, Migrate, Version,
+};
+
+/// The key that a [`Versioned`] item keeps its version under
+///
+/// The key is fixed for each type, so it is set by implementing this trait on a marker type and passing that to
+/// [`Versioned`]. It is read as a TOML dotted key, like [`ConfigMigrator::new`](crate::ConfigMigrator::new)'s version
+/// key. [`Versioned`] only implements `Debug`, `Clone`, `PartialEq` and `Eq` if the marker type does too.
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use toml_migrate::{build_migration_chain, ConfigMigrator, VersionKey, Versioned};
+/// # #[derive(Deserialize)] struct Profile { name: String }
+/// # build_migration_chain!(Profile = 2);
+/// #[derive(Debug, Clone, PartialEq, Eq)]
+/// struct SchemaKey;
+///
+/// impl VersionKey for SchemaKey {
+///     const KEY: &'static str = "meta.schema";
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     profiles: Vec<Versioned<Profile, SchemaKey>>,
+/// }
+/// # build_migration_chain!(Config = 1);
+///
+/// let config_str = "version = 1\n[[profiles]]\nmeta.schema = 2\nname = \"laptop\"\n";
+/// let (config, _) = ConfigMigrator::new("version").migrate_config::<Config>(config_str).unwrap();
+/// assert_eq!(config.profiles[0].name, "laptop");
+/// ```
+pub trait VersionKey {
+    /// The key, as a TOML dotted key
+    const KEY: &'static str;
+}
+
+/// The `version` key, which [`Versioned`] uses unless it is given another [`VersionKey`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DefaultVersionKey;
+
+impl VersionKey for DefaultVersionKey {
+    const KEY: &'static str = "version";
+}
+
+/// A table inside a config that has its own version key, and is migrated to `T` when it is deserialized
+///
+/// Useful for entries that get copied between configs, like `[[profiles]]`, so that a `Vec<Versioned<Profile>>` can
+/// hold a mix of old and new entries. Each item is migrated on its own with the chain ending in `T`, and fails to
+/// deserialize with the [`Error`](enum@Error) that
+/// [`ConfigMigrator::migrate_config`](crate::ConfigMigrator::migrate_config) would return. Serializing writes the item
+/// at the latest version, with the version key first.
+///
+/// The version key is `version` unless another one is set with a [`VersionKey`], and doesn't depend on the migrator
+/// the config is read with, so the migrator's aliases and default version don't apply to items either.
+///
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use toml_migrate::{build_migration_chain, ConfigMigrator, Version, Versioned};
+/// #[derive(Deserialize, Serialize)]
+/// struct ProfileV1 {
+///     name: String,
+/// }
+///
+/// #[derive(Deserialize, Serialize)]
+/// struct ProfileV2 {
+///     name: String,
+///     shell: String,
+/// }
+///
+/// impl From<ProfileV1> for ProfileV2 {
+///     fn from(prev: ProfileV1) -> Self {
+///         Self { name: prev.name, shell: "/bin/sh".to_owned() }
+///     }
+/// }
+///
+/// build_migration_chain!(ProfileV1 = 1, ProfileV2 = 2);
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     profiles: Vec<Versioned<ProfileV2>>,
+/// }
+///
+/// build_migration_chain!(Config = 1);
+///
+/// let config_str = "\
+/// version = 1
+///
+/// [[profiles]]
+/// version = 1
+/// name = \"laptop\"
+///
+/// [[profiles]]
+/// version = 2
+/// name = \"server\"
+/// shell = \"/bin/bash\"
+/// ";
+///
+/// let (config, _) = ConfigMigrator::new("version").migrate_config::<Config>(config_str).unwrap();
+///
+/// assert_eq!(config.profiles[0].shell, "/bin/sh");
+/// assert!(config.profiles[0].migrated());
+/// assert_eq!(config.profiles[1].version, Version::Int(2));
+/// assert!(!config.profiles[1].migrated());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Versioned<T, K = DefaultVersionKey> {
+    /// The item, migrated to the latest version
+    pub config: T,
+    /// Version of the item before it was migrated
+    pub version: Version,
+    key: PhantomData<K>,
+}
+
+impl<T: Migrate, K> Versioned<T, K> {
+    /// Wraps an item of the latest version
+    pub fn new(config: T) -> Self {
+        Self {
+            config,
+            version: T::VERSION,
+            key: PhantomData,
+        }
+    }
+
+    /// Whether the item had to be migrated
+    #[must_use]
+    pub fn migrated(&self) -> bool {
+        !T::VERSION.accepts(&self.version)
+    }
+}
+
+impl<T, K> Versioned<T, K> {
+    /// Unwraps the migrated item
+    pub fn into_inner(self) -> T {
+        self.config
+    }
+}
+
+impl<T, K> Deref for Versioned<T, K> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.config
+    }
+}
+
+impl<T, K> DerefMut for Versioned<T, K> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.config
+    }
+}
+
+impl<'de, T: Migrate, K: VersionKey> Deserialize<'de> for Versioned<T, K> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let RootTable(mut doc) = RootTable::deserialize(deserializer)?;
+
+        let item = path::remove(doc.as_table_mut(), &path::parse(K::KEY))
+            .ok_or_else(|| de::Error::custom(Error::MissingVersion))?;
+        let version = read_version::<T>(&item).ok_or_else(|| {
+            de::Error::custom(Error::MalformedVersion {
+                found: item.to_string().trim().to_owned(),
+            })
+        })?;
+
+        let config = T::migrate_from_doc(&version, doc).map_err(de::Error::custom)?;
+        Ok(Self {
+            config,
+            version,
+            key: PhantomData,
+        })
+    }
+}
+
+impl<T: Migrate + Serialize, K: VersionKey> Serialize for Versioned<T, K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut doc = toml_edit::ser::to_document(&self.config).map_err(ser::Error::custom)?;
+        path::insert(
+            doc.as_table_mut(),
+            &path::parse(K::KEY),
+            Item::Value(T::VERSION.to_value()),
+        );
+        serialize_item_as_toml(doc.as_item(), serializer)
+    }
+}
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use toml_migrate::{build_migration_chain, ConfigMigrator};
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigV1 {
+    timeout: u32,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ConfigV2 {
+    timeout: u32,
+    #[serde(default)]
+    retries: u8,
+}
+
+impl From<ConfigV1> for ConfigV2 {
+    fn from(prev: ConfigV1) -> Self {
+        Self {
+            timeout: prev.timeout,
+            retries: 4,
+        }
+    }
+}
+
+impl From<ConfigV2> for ConfigV1 {
+    fn from(next: ConfigV2) -> Self {
+        Self {
+            timeout: next.timeout,
+        }
+    }
+}
+
+build_migration_chain!(ConfigV1 = 1, rev ConfigV2 = 2);
+
+#[test]
+fn writes_only_keys_of_the_target_version() {
+    let migrator = ConfigMigrator::new("version");
+    let (downgraded, _, lost) = migrator
+        .downgrade_config::<ConfigV2>("version = 2\ntimeout = 5\nretries = 0\n", 1)
+        .unwrap();
+
+    assert_eq!(downgraded, "version = 1\ntimeout = 5\n");
+    assert_eq!(lost, ["retries"]);
+    assert!(migrator.migrate_config::<ConfigV1>(&downgraded).is_ok());
+}
+
+#[test]
+fn reports_unknown_keys_it_cant_keep() {
+    let migrator = ConfigMigrator::new("version");
+    let (downgraded, _, lost) = migrator
+        .downgrade_config::<ConfigV2>("version = 2\ntimeout = 5\nretries = 4\nnote = 1\n", 1)
+        .unwrap();
+
+    // `ConfigV1` doesn't accept unknown keys, so `note` can't be kept
+    assert_eq!(downgraded, "version = 1\ntimeout = 5\n");
+    assert_eq!(lost, ["note"]);
+}
+
+#[derive(Deserialize, Serialize)]
+struct LooseV1 {
+    timeout: u32,
+}
+
+#[derive(Deserialize, Serialize)]
+struct LooseV2 {
+    timeout: u32,
+}
+
+impl From<LooseV1> for LooseV2 {
+    fn from(prev: LooseV1) -> Self {
+        Self {
+            timeout: prev.timeout,
+        }
+    }
+}
+
+impl From<LooseV2> for LooseV1 {
+    fn from(next: LooseV2) -> Self {
+        Self {
+            timeout: next.timeout,
+        }
+    }
+}
+
+build_migration_chain!(LooseV1 = 1, rev LooseV2 = 2);
+
+#[test]
+fn keeps_unknown_keys_the_target_ignores() {
+    let (downgraded, _, lost) = ConfigMigrator::new("version")
+        .downgrade_config::<LooseV2>("version = 2\ntimeout = 5\nnote = 1 # mine\n", 1)
+        .unwrap();
+
+    assert_eq!(downgraded, "version = 1\ntimeout = 5\nnote = 1 # mine\n");
+    assert!(lost.is_empty());
+}
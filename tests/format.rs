@@ -0,0 +1,70 @@
+#![cfg(feature = "json")]
+
+use serde::{Deserialize, Serialize};
+use toml_migrate::{build_migration_chain, ConfigMigrator, Format};
+
+#[derive(Deserialize, Serialize)]
+struct Server {
+    host: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Profile {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ConfigV1 {
+    server: Server,
+    profiles: Vec<Profile>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ConfigV2 {
+    server: Server,
+    profiles: Vec<Profile>,
+    retries: u8,
+}
+
+impl From<ConfigV1> for ConfigV2 {
+    fn from(prev: ConfigV1) -> Self {
+        Self {
+            server: prev.server,
+            profiles: prev.profiles,
+            retries: 4,
+        }
+    }
+}
+
+build_migration_chain!(ConfigV1: Json = 1, ConfigV2: Toml = 2);
+
+#[test]
+fn writes_standard_tables_when_converting_to_toml() {
+    let config_str = r#"{
+        "version": 1,
+        "server": { "host": "localhost" },
+        "profiles": [{ "name": "laptop" }, { "name": "server" }]
+    }"#;
+
+    let (_, _, migrated) = ConfigMigrator::new("version")
+        .with_format(Format::Json)
+        .migrate_config_preserving::<ConfigV2>(config_str)
+        .unwrap();
+
+    assert_eq!(
+        migrated.unwrap(),
+        "\
+version = 2
+retries = 4
+
+[server]
+host = \"localhost\"
+
+[[profiles]]
+name = \"laptop\"
+
+[[profiles]]
+name = \"server\"
+",
+    );
+}
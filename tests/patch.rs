@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use toml_migrate::{build_migration_chain, Change, ConfigMigrator};
+
+#[derive(Deserialize, Serialize)]
+struct ConfigV1 {
+    host: String,
+    port: u16,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Server {
+    host: String,
+    port: u16,
+    tls: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ConfigV2 {
+    server: Server,
+}
+
+impl From<ConfigV1> for ConfigV2 {
+    fn from(prev: ConfigV1) -> Self {
+        Self {
+            server: Server {
+                host: prev.host,
+                port: prev.port,
+                tls: false,
+            },
+        }
+    }
+}
+
+build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+
+#[derive(Deserialize, Serialize)]
+struct Legacy {
+    retries: u8,
+    backoff: u8,
+}
+
+#[derive(Deserialize, Serialize)]
+struct LegacyV1 {
+    name: String,
+    legacy: Legacy,
+}
+
+#[derive(Deserialize, Serialize)]
+struct LegacyV2 {
+    name: String,
+}
+
+impl From<LegacyV1> for LegacyV2 {
+    fn from(prev: LegacyV1) -> Self {
+        Self { name: prev.name }
+    }
+}
+
+build_migration_chain!(LegacyV1 = 1, LegacyV2 = 2);
+
+#[test]
+fn moves_keys_into_new_tables_like_the_dry_run() {
+    let config_str = "version = 1\nhost = \"localhost\"\nport = 8080\n";
+    let migrator = ConfigMigrator::new("version");
+    let (_, patch) = migrator.migration_patch::<ConfigV2>(config_str).unwrap();
+    let dry_run = migrator.dry_run::<ConfigV2>(config_str).unwrap();
+
+    assert_eq!(
+        serde_json::to_value(&patch).unwrap(),
+        serde_json::json!([
+            { "op": "replace", "path": "/version", "value": 2 },
+            { "op": "add", "path": "/server", "value": {} },
+            { "op": "move", "from": "/host", "path": "/server/host" },
+            { "op": "move", "from": "/port", "path": "/server/port" },
+            { "op": "add", "path": "/server/tls", "value": false },
+        ]),
+    );
+    assert_eq!(
+        dry_run.changes,
+        [
+            Change::Changed {
+                key: "version".into(),
+                from: "1".into(),
+                to: "2".into(),
+            },
+            Change::Renamed {
+                from: "host".into(),
+                to: "server.host".into(),
+                value: "\"localhost\"".into(),
+            },
+            Change::Renamed {
+                from: "port".into(),
+                to: "server.port".into(),
+                value: "8080".into(),
+            },
+            Change::Added {
+                key: "server.tls".into(),
+                value: "false".into(),
+            },
+        ],
+    );
+}
+
+#[test]
+fn removes_whole_tables_at_once() {
+    let config_str = "version = 1\nname = \"app\"\n\n[legacy]\nretries = 3\nbackoff = 2\n";
+    let migrator = ConfigMigrator::new("version");
+    let (_, patch) = migrator.migration_patch::<LegacyV2>(config_str).unwrap();
+    let dry_run = migrator.dry_run::<LegacyV2>(config_str).unwrap();
+
+    assert_eq!(
+        serde_json::to_value(&patch).unwrap(),
+        serde_json::json!([
+            { "op": "replace", "path": "/version", "value": 2 },
+            { "op": "remove", "path": "/legacy" },
+        ]),
+    );
+    assert_eq!(
+        dry_run.changes[1..],
+        [
+            Change::Removed {
+                key: "legacy.retries".into(),
+                value: "3".into(),
+            },
+            Change::Removed {
+                key: "legacy.backoff".into(),
+                value: "2".into(),
+            },
+        ],
+    );
+}
@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+use toml_migrate::{build_migration_chain, Change, ConfigMigrator, VersionAlias};
+
+#[derive(Deserialize, Serialize)]
+struct ConfigV1 {
+    timeout_secs: u32,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ConfigV2 {
+    timeout: u32,
+    retries: u8,
+}
+
+impl From<ConfigV1> for ConfigV2 {
+    fn from(prev: ConfigV1) -> Self {
+        Self {
+            timeout: prev.timeout_secs,
+            retries: 4,
+        }
+    }
+}
+
+build_migration_chain!(ConfigV1 = 1, ConfigV2 = 2);
+
+fn migrate<T: toml_migrate::Migrate + Serialize>(
+    migrator: ConfigMigrator,
+    config_str: &str,
+) -> String {
+    let (_, _, migrated) = migrator.migrate_config_preserving::<T>(config_str).unwrap();
+    migrated.unwrap()
+}
+
+#[test]
+fn keeps_unknown_keys() {
+    let config_str = "\
+version = 1
+timeout_secs = 5
+note = \"added by hand\" # keep me
+
+[extra]
+foo = 1
+";
+
+    assert_eq!(
+        migrate::<ConfigV2>(ConfigMigrator::new("version"), config_str),
+        "\
+version = 2
+note = \"added by hand\" # keep me
+timeout = 5
+retries = 4
+
+[extra]
+foo = 1
+",
+    );
+}
+
+#[derive(Deserialize, Serialize)]
+struct LegacyV1 {
+    name: String,
+    #[serde(default)]
+    legacy: bool,
+    nickname: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct LegacyV2 {
+    name: String,
+}
+
+impl From<LegacyV1> for LegacyV2 {
+    fn from(prev: LegacyV1) -> Self {
+        Self { name: prev.name }
+    }
+}
+
+build_migration_chain!(LegacyV1 = 1, LegacyV2 = 2);
+
+#[test]
+fn drops_removed_fields_with_default_values() {
+    let migrator = ConfigMigrator::new("version");
+    let config_str = "version = 1\nname = \"x\"\nlegacy = false\nnickname = \"y\"\n";
+
+    let (_, _, migrated) = migrator
+        .migrate_config_preserving::<LegacyV2>(config_str)
+        .unwrap();
+    let migrated = migrated.unwrap();
+    assert_eq!(migrated, "version = 2\nname = \"x\"\n");
+    assert!(migrator.migrate_config::<LegacyV2>(&migrated).is_ok());
+
+    let changes = migrator.dry_run::<LegacyV2>(config_str).unwrap().changes;
+    assert!(changes.contains(&Change::Removed {
+        key: "legacy".to_owned(),
+        value: "false".to_owned(),
+    }));
+    assert!(changes.contains(&Change::Removed {
+        key: "nickname".to_owned(),
+        value: "\"y\"".to_owned(),
+    }));
+}
+
+#[derive(Deserialize, Serialize)]
+struct RetriesV1 {
+    #[serde(default)]
+    retries: u8,
+}
+
+#[derive(Deserialize, Serialize)]
+struct RetriesV2 {
+    attempts: u8,
+}
+
+impl From<RetriesV1> for RetriesV2 {
+    fn from(prev: RetriesV1) -> Self {
+        Self {
+            attempts: prev.retries,
+        }
+    }
+}
+
+build_migration_chain!(RetriesV1 = 1, RetriesV2 = 2);
+
+#[test]
+fn drops_renamed_fields_with_default_values() {
+    assert_eq!(
+        migrate::<RetriesV2>(ConfigMigrator::new("version"), "version = 1\nretries = 0\n"),
+        "version = 2\nattempts = 0\n",
+    );
+}
+
+#[test]
+fn keeps_siblings_of_nested_version_key() {
+    let config_str = "\
+timeout_secs = 5
+
+[meta]
+v = 1
+owner = \"x\"
+";
+
+    assert_eq!(
+        migrate::<ConfigV2>(ConfigMigrator::new("meta.v"), config_str),
+        "\
+timeout = 5
+retries = 4
+
+[meta]
+v = 2
+owner = \"x\"
+",
+    );
+}
+
+#[test]
+fn merges_inline_version_table() {
+    let config_str = "meta = { v = 1, owner = \"x\" } # meta\ntimeout_secs = 5\n";
+
+    assert_eq!(
+        migrate::<ConfigV2>(ConfigMigrator::new("meta.v"), config_str),
+        "meta = { v = 2, owner = \"x\" } # meta\ntimeout = 5\nretries = 4\n",
+    );
+}
+
+#[test]
+fn renames_version_alias_in_place() {
+    let migrator =
+        ConfigMigrator::new("version").with_version_alias(VersionAlias::new("config_version"));
+    let config_str = "# hdr\nconfig_version = 1 # old key\ntimeout_secs = 5\n";
+
+    assert_eq!(
+        migrate::<ConfigV2>(migrator, config_str),
+        "# hdr\nversion = 2 # old key\ntimeout = 5\nretries = 4\n",
+    );
+}
+
+#[derive(Deserialize, Serialize)]
+struct ServerV1 {
+    host: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ServerV2 {
+    host: String,
+    port: u16,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ServersV1 {
+    servers: Vec<ServerV1>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ServersV2 {
+    servers: Vec<ServerV2>,
+}
+
+impl From<ServersV1> for ServersV2 {
+    fn from(prev: ServersV1) -> Self {
+        let servers = prev
+            .servers
+            .into_iter()
+            .map(|server| ServerV2 {
+                host: server.host,
+                port: 80,
+            })
+            .collect();
+        Self { servers }
+    }
+}
+
+build_migration_chain!(ServersV1 = 1, ServersV2 = 2);
+
+#[test]
+fn merges_arrays_of_tables() {
+    let config_str = "\
+version = 1
+
+# Primary first
+[[servers]]
+host = \"a\" # primary
+
+[[servers]]
+host = \"b\"
+";
+
+    assert_eq!(
+        migrate::<ServersV2>(ConfigMigrator::new("version"), config_str),
+        "\
+version = 2
+
+# Primary first
+[[servers]]
+host = \"a\" # primary
+port = 80
+
+[[servers]]
+host = \"b\"
+port = 80
+",
+    );
+}
+
+#[derive(Deserialize, Serialize)]
+struct NestedV1 {
+    server: ServerV1,
+}
+
+#[derive(Deserialize, Serialize)]
+struct NestedV2 {
+    server: ServerV2,
+}
+
+impl From<NestedV1> for NestedV2 {
+    fn from(prev: NestedV1) -> Self {
+        let server = ServerV2 {
+            host: prev.server.host,
+            port: 80,
+        };
+        Self { server }
+    }
+}
+
+build_migration_chain!(NestedV1 = 1, NestedV2 = 2);
+
+#[test]
+fn merges_into_standard_tables() {
+    let config_str = "\
+version = 1
+
+[server] # the only one
+host = \"a\"
+";
+
+    assert_eq!(
+        migrate::<NestedV2>(ConfigMigrator::new("version"), config_str),
+        "\
+version = 2
+
+[server] # the only one
+host = \"a\"
+port = 80
+",
+    );
+}
+
+#[derive(Deserialize, Serialize)]
+struct DatesV1 {
+    created: toml_migrate::toml_edit::Datetime,
+}
+
+#[derive(Deserialize, Serialize)]
+struct DatesV2 {
+    created: toml_migrate::toml_edit::Datetime,
+    updated: Option<toml_migrate::toml_edit::Datetime>,
+}
+
+impl From<DatesV1> for DatesV2 {
+    fn from(prev: DatesV1) -> Self {
+        Self {
+            created: prev.created,
+            updated: Some(prev.created),
+        }
+    }
+}
+
+build_migration_chain!(DatesV1 = 1, DatesV2 = 2);
+
+#[test]
+fn keeps_unknown_keys_next_to_datetimes() {
+    assert_eq!(
+        migrate::<DatesV2>(
+            ConfigMigrator::new("version"),
+            "version = 1\ncreated = 1979-05-27\nnote = 1\n"
+        ),
+        "version = 2\ncreated = 1979-05-27\nnote = 1\nupdated = 1979-05-27\n",
+    );
+}
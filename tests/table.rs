@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use toml_migrate::toml_edit::DocumentMut;
+use toml_migrate::{build_migration_chain, ConfigMigrator, Error};
+
+#[derive(Deserialize, Serialize)]
+struct Config {
+    line_length: u32,
+}
+
+build_migration_chain!(Config = 2);
+
+const PYPROJECT: &str = "\
+[project]
+name = \"my-project\" # not ours
+
+[tool.my-tool]
+version = 2
+line_length = 100 # characters
+
+[tool.other-tool]
+line_length = 80
+";
+
+#[test]
+fn serializes_onto_the_table_only() {
+    let mut doc: DocumentMut = PYPROJECT.parse().unwrap();
+    ConfigMigrator::new("version")
+        .with_table("tool.my-tool")
+        .serialize_config_onto(&Config { line_length: 120 }, &mut doc)
+        .unwrap();
+
+    assert_eq!(
+        doc.to_string(),
+        PYPROJECT.replace("line_length = 100", "line_length = 120"),
+    );
+}
+
+#[test]
+fn fails_to_serialize_onto_a_missing_table() {
+    let mut doc: DocumentMut = PYPROJECT.parse().unwrap();
+    let result = ConfigMigrator::new("version")
+        .with_table("tool.missing")
+        .serialize_config_onto(&Config { line_length: 120 }, &mut doc);
+
+    assert!(matches!(result, Err(Error::MissingTable { path }) if path == "tool.missing"));
+    assert_eq!(doc.to_string(), PYPROJECT);
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use toml_migrate::toml_edit::{self, Datetime};
+use toml_migrate::{build_migration_chain, Error, VersionKey, Versioned};
+
+#[derive(Deserialize, Serialize)]
+struct ProfileV1 {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ProfileV2 {
+    name: String,
+    created: Datetime,
+}
+
+impl From<ProfileV1> for ProfileV2 {
+    fn from(prev: ProfileV1) -> Self {
+        Self {
+            name: prev.name,
+            created: "1979-05-27T07:32:00Z".parse().unwrap(),
+        }
+    }
+}
+
+build_migration_chain!(ProfileV1 = 1, ProfileV2 = 2);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SchemaKey;
+
+impl VersionKey for SchemaKey {
+    const KEY: &'static str = "meta.schema";
+}
+
+#[derive(Deserialize, Serialize)]
+struct Config {
+    profile: Versioned<ProfileV2>,
+    other: Versioned<ProfileV2, SchemaKey>,
+}
+
+#[test]
+fn writes_version_first_and_keeps_datetimes() {
+    let config_str = "\
+[profile]
+version = 1
+name = \"laptop\"
+
+[other]
+meta.schema = 1
+name = \"server\"
+";
+
+    let config: Config = toml_edit::de::from_str(config_str).unwrap();
+    assert!(config.profile.migrated());
+    assert!(config.other.migrated());
+
+    assert_eq!(
+        toml_edit::ser::to_string(&config).unwrap(),
+        "\
+profile = { version = 2, name = \"laptop\", created = 1979-05-27T07:32:00Z }
+other = { meta = { schema = 2 }, name = \"server\", created = 1979-05-27T07:32:00Z }
+",
+    );
+}
+
+#[test]
+fn custom_key_replaces_version() {
+    let config_str = "profile = { version = 2, name = \"a\", created = 1979-05-27 }\nother = { version = 2, name = \"b\" }\n";
+    let Err(err) = toml_edit::de::from_str::<Config>(config_str) else {
+        panic!("`other` has no `meta.schema` key");
+    };
+
+    assert_eq!(err.message(), Error::MissingVersion.to_string());
+    assert_eq!(
+        &config_str[err.span().unwrap()],
+        "{ version = 2, name = \"b\" }"
+    );
+}
@@ -0,0 +1,406 @@
+//! Derive macro for the `Migrate` trait of [toml-migrate](https://crates.io/crates/toml-migrate)
+//!
+//! Don't depend on this crate directly, but enable the `derive` feature of `toml-migrate` instead.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::{
+    parenthesized, parse::ParseStream, parse_macro_input, punctuated::Punctuated, spanned::Spanned,
+    DeriveInput, Expr, Ident, Path, Token, Type,
+};
+
+/// Implements `Migrate` for a config struct, as an alternative to `build_migration_chain!`
+///
+/// Every struct in the chain is configured with a `#[migrate(...)]` attribute:
+///
+/// - `version = ...`: the version of this struct, as an integer, string or `SemVer`. Any constant expression works,
+///   so versions can be defined as consts.
+/// - `from = Type`: the previous struct in the chain, which is converted with `From`. Left out for the oldest struct.
+/// - `try_from = Type`: like `from`, but converted with `TryFrom`.
+/// - `format = Json`: the format configs of this version are written in, as a variant of `Format`. Left out if it's
+///   the same for every version.
+/// - `downgrade`: also converts back into the previous struct with `From`, which lets configs be downgraded.
+/// - `doc_steps(1 => v1_to_v2, 2 => v2_to_v3)`: document-level steps leading up to the oldest struct. A step can be
+///   given a downgrade function after a `|`, like `1 => v1_to_v2 | v2_to_v1`.
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use toml_migrate::Migrate;
+/// #[derive(Deserialize, Migrate)]
+/// #[migrate(version = 1)]
+/// struct ConfigV1 {}
+///
+/// #[derive(Deserialize, Migrate)]
+/// #[migrate(version = 2, from = ConfigV1)]
+/// struct ConfigV2 {}
+/// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+/// ```
+///
+/// Each struct checks at compile time that its version is newer than the one of the struct it migrates from:
+///
+/// ```compile_fail,E0080
+/// # use serde::Deserialize;
+/// # use toml_migrate::Migrate;
+/// # #[derive(Deserialize, Migrate)]
+/// # #[migrate(version = 2)]
+/// # struct ConfigV1 {}
+/// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+/// // error: `ConfigV2` must have a newer version than `ConfigV1`, which it migrates from
+/// #[derive(Deserialize, Migrate)]
+/// #[migrate(version = 2, from = ConfigV1)]
+/// struct ConfigV2 {}
+/// ```
+///
+/// Attributes that are given twice or don't fit together are errors as well:
+///
+/// ```compile_fail
+/// # use serde::Deserialize;
+/// # use toml_migrate::Migrate;
+/// # #[derive(Deserialize, Migrate)]
+/// # #[migrate(version = 1)]
+/// # struct ConfigV1 {}
+/// # impl From<ConfigV1> for ConfigV2 { fn from(_: ConfigV1) -> Self { Self {} } }
+/// // error: duplicate `version`
+/// #[derive(Deserialize, Migrate)]
+/// #[migrate(version = 2, version = 3, from = ConfigV1)]
+/// struct ConfigV2 {}
+/// ```
+///
+/// ```compile_fail
+/// # use serde::Deserialize;
+/// # use toml_migrate::Migrate;
+/// # use toml_migrate::toml_edit::DocumentMut;
+/// # #[derive(Deserialize, Migrate)]
+/// # #[migrate(version = 2)]
+/// # struct ConfigV2 {}
+/// # impl From<ConfigV2> for ConfigV3 { fn from(_: ConfigV2) -> Self { Self {} } }
+/// # fn v1_to_v2(_: &mut DocumentMut) {}
+/// // error: `doc_steps` can only be used on the oldest struct in the chain, which has no `from`
+/// #[derive(Deserialize, Migrate)]
+/// #[migrate(version = 3, from = ConfigV2, doc_steps(1 => v1_to_v2))]
+/// struct ConfigV3 {}
+/// ```
+///
+/// ```compile_fail
+/// # use serde::Deserialize;
+/// # use toml_migrate::Migrate;
+/// // error: `downgrade` needs a previous struct to downgrade to, given with `from` or `try_from`
+/// #[derive(Deserialize, Migrate)]
+/// #[migrate(version = 1, downgrade)]
+/// struct ConfigV1 {}
+/// ```
+#[proc_macro_derive(Migrate, attributes(migrate))]
+pub fn derive_migrate(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+enum Step {
+    First(Vec<DocStep>),
+    From(Type),
+    TryFrom(Type),
+}
+
+struct DocStep {
+    version: Expr,
+    migrate: Path,
+    downgrade: Option<Path>,
+}
+
+struct Attrs {
+    version: Expr,
+    step: Step,
+    downgrade: bool,
+    format: Option<Ident>,
+}
+
+fn parse_attrs(input: &DeriveInput) -> syn::Result<Attrs> {
+    let mut version = None;
+    let mut from = None;
+    let mut doc_steps = None;
+    let mut downgrade = None;
+    let mut format = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("migrate"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("version") {
+                if version.is_some() {
+                    return Err(meta.error("duplicate `version`"));
+                }
+                version = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("from") || meta.path.is_ident("try_from") {
+                if from.is_some() {
+                    return Err(meta.error("only one of `from` and `try_from` can be given"));
+                }
+                let ty: Type = meta.value()?.parse()?;
+                from = Some(if meta.path.is_ident("from") {
+                    Step::From(ty)
+                } else {
+                    Step::TryFrom(ty)
+                });
+            } else if meta.path.is_ident("doc_steps") {
+                if doc_steps.is_some() {
+                    return Err(meta.error("duplicate `doc_steps`"));
+                }
+                let content;
+                parenthesized!(content in meta.input);
+                let steps = Punctuated::<DocStep, Token![,]>::parse_terminated_with(
+                    &content,
+                    parse_doc_step,
+                )?;
+                doc_steps = Some((meta.path.span(), steps.into_iter().collect::<Vec<_>>()));
+            } else if meta.path.is_ident("format") {
+                if format.is_some() {
+                    return Err(meta.error("duplicate `format`"));
+                }
+                format = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("downgrade") {
+                if downgrade.is_some() {
+                    return Err(meta.error("duplicate `downgrade`"));
+                }
+                downgrade = Some(meta.path.span());
+            } else {
+                return Err(meta.error(
+                    "expected `version`, `from`, `try_from`, `format`, `downgrade` or `doc_steps`",
+                ));
+            }
+            Ok(())
+        })?;
+    }
+
+    let version = version.ok_or_else(|| {
+        syn::Error::new_spanned(
+            &input.ident,
+            "missing `#[migrate(version = ...)]` attribute",
+        )
+    })?;
+
+    let step = match (from, doc_steps) {
+        (Some(_), Some((span, _))) => return Err(syn::Error::new(
+            span,
+            "`doc_steps` can only be used on the oldest struct in the chain, which has no `from`",
+        )),
+        (Some(step), None) => step,
+        (None, doc_steps) => Step::First(doc_steps.map(|(_, steps)| steps).unwrap_or_default()),
+    };
+
+    if let (Step::First(_), Some(span)) = (&step, downgrade) {
+        return Err(syn::Error::new(
+            span,
+            "`downgrade` needs a previous struct to downgrade to, given with `from` or `try_from`",
+        ));
+    }
+
+    Ok(Attrs {
+        version,
+        step,
+        downgrade: downgrade.is_some(),
+        format,
+    })
+}
+
+fn parse_doc_step(input: ParseStream) -> syn::Result<DocStep> {
+    let version = input.parse()?;
+    input.parse::<Token![=>]>()?;
+    let migrate = input.parse()?;
+    let downgrade = if input.parse::<Option<Token![|]>>()?.is_some() {
+        Some(input.parse()?)
+    } else {
+        None
+    };
+
+    Ok(DocStep {
+        version,
+        migrate,
+        downgrade,
+    })
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let Attrs {
+        version,
+        step,
+        downgrade,
+        format,
+    } = parse_attrs(input)?;
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut where_clause = where_clause
+        .cloned()
+        .unwrap_or_else(|| syn::parse_quote!(where));
+    if !input.generics.params.is_empty() {
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(Self: ::toml_migrate::__private::serde::de::DeserializeOwned + 'static));
+    }
+
+    let version = quote! {
+        ::toml_migrate::__private::Lit(#version).into_version()
+    };
+
+    let downgrade = match &step {
+        Step::From(prev) | Step::TryFrom(prev) if downgrade => quote! {
+            const DOWNGRADE: ::core::option::Option<::toml_migrate::Downgrade<Self>> =
+                ::core::option::Option::Some(|config, target| {
+                    ::toml_migrate::__private::downgrade::<#prev, Self>(config, target)
+                });
+        },
+        _ => TokenStream2::new(),
+    };
+
+    let format = format.map(|format| {
+        quote! {
+            const FORMAT: ::core::option::Option<::toml_migrate::Format> =
+                ::core::option::Option::Some(::toml_migrate::Format::#format);
+        }
+    });
+
+    let body = match &step {
+        Step::First(doc_steps) => {
+            let doc_versions = doc_steps.iter().map(|step| &step.version);
+            let doc_migrates = doc_steps.iter().map(|step| &step.migrate);
+            let doc_names = doc_steps.iter().map(|step| tokens_string(&step.migrate));
+            let doc_downgrades = doc_steps.iter().map(|step| match &step.downgrade {
+                Some(downgrade) => quote!(::core::option::Option::Some(|doc| #downgrade(doc))),
+                None => quote!(::core::option::Option::None),
+            });
+            let oldest = match doc_steps.first() {
+                Some(oldest) => {
+                    let oldest = &oldest.version;
+                    quote!(::toml_migrate::__private::Lit(#oldest).into_version())
+                }
+                None => version.clone(),
+            };
+
+            quote! {
+                type From = Self;
+                const VERSION: ::toml_migrate::Version = #version;
+                const OLDEST_VERSION: ::toml_migrate::Version = #oldest;
+                const DOC_MIGRATIONS: &'static [::toml_migrate::DocMigration] = &[
+                    #(::toml_migrate::DocMigration {
+                        version: ::toml_migrate::__private::Lit(#doc_versions).into_version(),
+                        migrate: |doc| #doc_migrates(doc),
+                        name: #doc_names,
+                        downgrade: #doc_downgrades,
+                    }),*
+                ];
+
+                fn migrate_from(prev: Self) -> ::core::result::Result<Self, ::toml_migrate::Error> {
+                    ::core::result::Result::Ok(prev)
+                }
+            }
+        }
+        Step::From(prev) => quote! {
+            type From = #prev;
+            const VERSION: ::toml_migrate::Version = #version;
+            #downgrade
+
+            fn migrate_from(prev: #prev) -> ::core::result::Result<Self, ::toml_migrate::Error> {
+                ::core::result::Result::Ok(::core::convert::Into::into(prev))
+            }
+        },
+        Step::TryFrom(prev) => quote! {
+            type From = #prev;
+            const VERSION: ::toml_migrate::Version = #version;
+            #downgrade
+
+            fn migrate_from(prev: #prev) -> ::core::result::Result<Self, ::toml_migrate::Error> {
+                <Self as ::core::convert::TryFrom<#prev>>::try_from(prev).map_err(|err| {
+                    ::toml_migrate::Error::Migration {
+                        from: <#prev as ::toml_migrate::Migrate>::VERSION,
+                        to: <Self as ::toml_migrate::Migrate>::VERSION,
+                        source: ::core::convert::Into::into(err),
+                    }
+                })
+            }
+        },
+    };
+
+    // Generic structs can't be named in a free-standing const, so only concrete ones get checked
+    let check = match &step {
+        Step::From(prev) | Step::TryFrom(prev) if input.generics.params.is_empty() => {
+            let message = format!(
+                "`{}` must have a newer version than `{}`, which it migrates from",
+                name,
+                tokens_string(prev),
+            );
+            quote! {
+                const _: () = ::core::assert!(
+                    ::toml_migrate::__private::is_newer(
+                        <#name as ::toml_migrate::Migrate>::VERSION,
+                        <#prev as ::toml_migrate::Migrate>::VERSION,
+                    ),
+                    #message,
+                );
+            }
+        }
+        Step::First(doc_steps) => {
+            let pairs = doc_steps.windows(2).map(|pair| {
+                let (prev_version, next_version) = (&pair[0].version, &pair[1].version);
+                let message = format!(
+                    "the step `{}` must have a newer version than `{}`, which runs before it",
+                    tokens_string(&pair[1].migrate),
+                    tokens_string(&pair[0].migrate),
+                );
+                quote! {
+                    const _: () = ::core::assert!(
+                        ::toml_migrate::__private::is_newer(
+                            ::toml_migrate::__private::Lit(#next_version).into_version(),
+                            ::toml_migrate::__private::Lit(#prev_version).into_version(),
+                        ),
+                        #message,
+                    );
+                }
+            });
+            let last = doc_steps
+                .last()
+                .filter(|_| input.generics.params.is_empty())
+                .map(|prev| {
+                    let prev_version = &prev.version;
+                    let message = format!(
+                        "`{}` must have a newer version than the step `{}`, which runs before it",
+                        name,
+                        tokens_string(&prev.migrate),
+                    );
+                    quote! {
+                        const _: () = ::core::assert!(
+                            ::toml_migrate::__private::is_newer(
+                                <#name as ::toml_migrate::Migrate>::VERSION,
+                                ::toml_migrate::__private::Lit(#prev_version).into_version(),
+                            ),
+                            #message,
+                        );
+                    }
+                });
+
+            quote! {
+                #(#pairs)*
+                #last
+            }
+        }
+        _ => TokenStream2::new(),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::toml_migrate::Migrate for #name #ty_generics #where_clause {
+            #body
+            #format
+        }
+
+        #check
+    })
+}
+
+/// Writes out a type or path the way it would be written in code, for error messages
+fn tokens_string(tokens: impl ToTokens) -> String {
+    tokens.to_token_stream().to_string().replace(' ', "")
+}
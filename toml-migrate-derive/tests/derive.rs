@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use toml_migrate::toml_edit::DocumentMut;
+use toml_migrate::{ConfigMigrator, Error, Format, Migrate, Version};
+
+fn v2_to_v3(doc: &mut DocumentMut) {
+    if let Some(timeout) = doc.remove("timeout_secs") {
+        doc.insert("timeout", timeout);
+    }
+}
+
+fn v3_to_v2(doc: &mut DocumentMut) {
+    if let Some(timeout) = doc.remove("timeout") {
+        doc.insert("timeout_secs", timeout);
+    }
+}
+
+#[derive(Deserialize, Serialize, Migrate)]
+#[migrate(version = 3, doc_steps(2 => v2_to_v3 | v3_to_v2))]
+struct ConfigV3 {
+    timeout: u32,
+}
+
+#[derive(Deserialize, Serialize, Migrate)]
+#[migrate(version = 4, from = ConfigV3, downgrade)]
+struct ConfigV4 {
+    timeout: u32,
+    retries: u8,
+}
+
+impl From<ConfigV3> for ConfigV4 {
+    fn from(prev: ConfigV3) -> Self {
+        Self {
+            timeout: prev.timeout,
+            retries: 4,
+        }
+    }
+}
+
+impl From<ConfigV4> for ConfigV3 {
+    fn from(next: ConfigV4) -> Self {
+        Self {
+            timeout: next.timeout,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Migrate)]
+#[migrate(version = 5, try_from = ConfigV4, format = Toml)]
+struct ConfigV5 {
+    timeout: u32,
+    retries: u8,
+}
+
+impl TryFrom<ConfigV4> for ConfigV5 {
+    type Error = String;
+
+    fn try_from(prev: ConfigV4) -> Result<Self, String> {
+        if prev.retries > 10 {
+            return Err(format!("{} retries is too many", prev.retries));
+        }
+        Ok(Self {
+            timeout: prev.timeout,
+            retries: prev.retries,
+        })
+    }
+}
+
+#[test]
+fn migrates_through_every_kind_of_step() {
+    let (config, outcome) = ConfigMigrator::new("version")
+        .migrate_config::<ConfigV5>("version = 2\ntimeout_secs = 60\n")
+        .unwrap();
+
+    assert_eq!((config.timeout, config.retries), (60, 4));
+    assert_eq!(outcome.path, (2..=5).map(Version::Int).collect::<Vec<_>>());
+    assert_eq!(ConfigV5::OLDEST_VERSION, Version::Int(2));
+    assert_eq!(ConfigV5::FORMAT, Some(Format::Toml));
+    assert_eq!(ConfigV4::FORMAT, None);
+}
+
+#[test]
+fn fails_try_from_steps() {
+    let result = ConfigMigrator::new("version")
+        .migrate_config::<ConfigV5>("version = 4\ntimeout = 60\nretries = 11\n");
+
+    assert!(matches!(
+        result,
+        Err(Error::Migration {
+            from: Version::Int(4),
+            to: Version::Int(5),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn downgrades_through_doc_steps() {
+    let (downgraded, outcome, lost) = ConfigMigrator::new("version")
+        .downgrade_config::<ConfigV4>("version = 4\ntimeout = 60\nretries = 8\n", 2)
+        .unwrap();
+
+    assert_eq!(downgraded, "version = 2\ntimeout_secs = 60\n");
+    assert_eq!(
+        outcome.path,
+        (2..=4).rev().map(Version::Int).collect::<Vec<_>>()
+    );
+    assert_eq!(lost, ["retries"]);
+}